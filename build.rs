@@ -1,3 +1,5 @@
 fn main() {
+    // 输出 esp-idf 的配置, sdkconfig 中打开的选项成为 `esp_idf_*` cfg, 例如 `esp_idf_spiram`
     embuild::espidf::sysenv::output();
+    println!("cargo:rustc-check-cfg=cfg(esp_idf_spiram)");
 }
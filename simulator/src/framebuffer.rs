@@ -0,0 +1,97 @@
+//! 内存中的 RGB565 帧缓存, 代替屏幕作为绘制目标
+
+use anyhow::Result;
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{OriginDimensions, Size},
+    pixelcolor::{Rgb565, Rgb888},
+    prelude::RgbColor,
+    Pixel,
+};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+pub struct Framebuffer {
+    size: Size,
+    pixels: Vec<Rgb565>,
+    /// 画到屏幕外面的像素数, 屏幕上会被截掉
+    outside: usize,
+}
+
+impl Framebuffer {
+    /// 和屏幕上电后一样, 初始内容为黑色
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            size: Size::new(width, height),
+            pixels: vec![Rgb565::BLACK; (width * height) as usize],
+            outside: 0,
+        }
+    }
+
+    pub fn outside_pixels(&self) -> usize {
+        self.outside
+    }
+
+    /// 转换为 8 位 RGB, 每个像素 3 个字节
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|color| {
+                let color = Rgb888::from(*color);
+                [color.r(), color.g(), color.b()]
+            })
+            .collect()
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(writer, self.size.width, self.size.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.to_rgb8())?;
+        Ok(())
+    }
+}
+
+impl OriginDimensions for Framebuffer {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl DrawTarget for Framebuffer {
+    type Color = Rgb565;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x >= 0
+                && point.y >= 0
+                && (point.x as u32) < self.size.width
+                && (point.y as u32) < self.size.height
+            {
+                self.pixels[(point.y as u32 * self.size.width + point.x as u32) as usize] = color;
+            } else {
+                self.outside += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 读取 PNG 快照, 返回尺寸和 8 位 RGB 像素
+pub fn load_png(path: &Path) -> Result<(u32, u32, Vec<u8>)> {
+    let decoder = png::Decoder::new(File::open(path)?);
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    if info.color_type != png::ColorType::Rgb || info.bit_depth != png::BitDepth::Eight {
+        anyhow::bail!("{} is not an 8-bit RGB png", path.display());
+    }
+    buf.truncate(info.buffer_size());
+    Ok((info.width, info.height, buf))
+}
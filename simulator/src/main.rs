@@ -0,0 +1,363 @@
+//! 屏幕界面模拟器, 在 PC 上把各个画面绘制到内存中的帧缓存并保存为 PNG,
+//! 修改界面布局后不用烧录固件就能检查效果.
+//!
+//! - `cargo run`: 快照输出到 `target/snapshots`
+//! - `cargo run -- --check`: 和 `golden/` 中的快照比较, 有差异时返回错误
+//! - `cargo run -- --bless`: 用当前的绘制结果更新 `golden/`
+//! - `cargo test`: 运行固件中与硬件无关的模块的测试, 见 `tests/`
+//!
+//! 界面代码直接引用固件的源文件, 这些模块不能依赖 esp-idf.
+
+#[allow(dead_code)]
+#[path = "../../src/button.rs"]
+mod button;
+#[allow(dead_code)]
+#[path = "../../src/canvas.rs"]
+mod canvas;
+#[allow(dead_code)]
+#[path = "../../src/chart.rs"]
+mod chart;
+#[allow(dead_code)]
+#[path = "../../src/clock.rs"]
+mod clock;
+#[allow(dead_code)]
+#[path = "../../src/dashboard.rs"]
+mod dashboard;
+#[allow(dead_code)]
+#[path = "../../src/font.rs"]
+mod font;
+mod framebuffer;
+#[allow(dead_code)]
+#[path = "../../src/history.rs"]
+mod history;
+#[allow(dead_code)]
+#[path = "../../src/image_loader.rs"]
+mod image_loader;
+#[allow(dead_code)]
+#[path = "../../src/pages.rs"]
+mod pages;
+#[allow(dead_code)]
+#[path = "../../src/qr.rs"]
+mod qr;
+#[allow(dead_code)]
+#[path = "../../src/sprite.rs"]
+mod sprite;
+#[allow(dead_code)]
+#[path = "../../src/telemetry.rs"]
+mod telemetry;
+#[cfg(test)]
+mod tests;
+#[allow(dead_code)]
+#[path = "../../src/ui.rs"]
+mod ui;
+#[allow(dead_code)]
+#[path = "../../src/widget.rs"]
+mod widget;
+
+use anyhow::{anyhow, Result};
+use canvas::{Canvas, DisplayCommand};
+use dashboard::{Dashboard, DashboardData};
+use embedded_graphics::{
+    geometry::{Dimensions, Point},
+    pixelcolor::Rgb565,
+    prelude::RgbColor,
+};
+use framebuffer::Framebuffer;
+use history::{HistoryRange, TemperatureHistory};
+use log::Level;
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use widget::InputEvent;
+
+/// 需要检查的屏幕尺寸: ST7789 竖屏, ST7789 横屏, ST7735
+const PANEL_SIZES: [(u32, u32); 3] = [(240, 320), (320, 240), (128, 160)];
+
+type Scene = fn(&mut Framebuffer) -> Result<()>;
+
+/// 每个画面的名字和绘制函数
+const SCENES: [(&str, Scene); 17] = [
+    ("dashboard", draw_dashboard),
+    ("ferris_jump", draw_ferris_jump),
+    ("pairing", draw_pairing),
+    ("log", draw_log),
+    ("canvas", draw_canvas),
+    ("ui_status", draw_ui_status),
+    ("ui_settings", draw_ui_settings),
+    ("ui_info", draw_ui_info),
+    ("chart_5m", draw_chart_minutes),
+    ("chart_1h", draw_chart_hour),
+    ("qr_web", draw_qr_web),
+    ("qr_wifi", draw_qr_wifi),
+    ("clock_digital", draw_clock_digital),
+    ("clock_analog", draw_clock_analog),
+    ("clock_unsynced", draw_clock_unsynced),
+    ("boot", draw_boot),
+    ("fatal", draw_fatal),
+];
+
+fn sample_dashboard_data() -> DashboardData {
+    DashboardData {
+        ip: Some(Ipv4Addr::new(192, 168, 1, 50)),
+        rssi: Some(-52),
+        ble_connections: 1,
+        temperature: 41.5,
+        uptime: 93_784,
+        fat_usage: Some((1_258_291, 12_582_912)),
+    }
+}
+
+fn draw_dashboard(display: &mut Framebuffer) -> Result<()> {
+    ui::draw_home(display)?;
+    Dashboard::default().draw(display, &sample_dashboard_data(), None, None)
+}
+
+/// 跳到最高时的 ferris, 检查裁剪区域没有覆盖状态面板
+fn draw_ferris_jump(display: &mut Framebuffer) -> Result<()> {
+    ui::draw_home(display)?;
+    let mut ferris = ui::ferris_animation()?;
+    let start = Instant::now();
+    let frame = Duration::from_millis(100);
+    for i in 0..=4 {
+        ferris.update(display, start + frame * i)?;
+    }
+    ui::draw_passkey(display, None)?;
+    Dashboard::default().draw(display, &sample_dashboard_data(), None, None)
+}
+
+fn draw_pairing(display: &mut Framebuffer) -> Result<()> {
+    ui::draw_home(display)?;
+    ui::draw_passkey(display, Some(123_456))?;
+    let covered = ui::passkey_area(display.bounding_box());
+    Dashboard::default().draw(display, &sample_dashboard_data(), None, Some(covered))
+}
+
+fn draw_log(display: &mut Framebuffer) -> Result<()> {
+    let records = [
+        (Level::Info, "board init success".to_string()),
+        (Level::Info, "http server running".to_string()),
+        (Level::Debug, "ble advertising started".to_string()),
+        (
+            Level::Warn,
+            "ui font not loaded, use ascii font: No such file or directory".to_string(),
+        ),
+        (
+            Level::Error,
+            "display configure failed: invalid rotation".to_string(),
+        ),
+        (Level::Trace, "telemetry notify 12 bytes".to_string()),
+    ];
+    ui::draw_log_screen(display, &records)
+}
+
+fn draw_canvas(display: &mut Framebuffer) -> Result<()> {
+    let mut canvas = Canvas::default();
+    let commands = [
+        DisplayCommand::Clear(canvas::parse_color("003366")?),
+        DisplayCommand::Text {
+            text: "Room 2".to_string(),
+            position: Point::new(8, 8),
+            color: Rgb565::WHITE,
+            size: 2,
+        },
+        DisplayCommand::Text {
+            text: "Free until 14:00".to_string(),
+            position: Point::new(8, 36),
+            color: canvas::parse_color("66ff66")?,
+            size: 1,
+        },
+    ];
+    for command in commands {
+        canvas.execute(display, command, None)?;
+    }
+    Ok(())
+}
+
+/// 填好示例数据的多页面界面, 先按顺序处理输入事件再绘制
+fn draw_pages(display: &mut Framebuffer, events: &[InputEvent]) -> Result<()> {
+    let mut ui = pages::build();
+    let data = sample_dashboard_data();
+    pages::update_status(&mut ui, &data);
+    pages::update_settings(
+        &mut ui,
+        &pages::Settings {
+            rotation: Some(0),
+            mirrored: false,
+            interval_ms: 1000,
+        },
+    );
+    pages::update_info(
+        &mut ui,
+        &pages::Info {
+            version: "0.1.0",
+            panel: "240x320".to_string(),
+            free_heap: 180_224,
+            ip: data.ip,
+        },
+    );
+    for event in events {
+        ui.handle(*event);
+    }
+    ui.draw(display)
+}
+
+fn draw_ui_status(display: &mut Framebuffer) -> Result<()> {
+    draw_pages(display, &[])
+}
+
+fn draw_ui_settings(display: &mut Framebuffer) -> Result<()> {
+    draw_pages(display, &[InputEvent::Right, InputEvent::Down])
+}
+
+fn draw_ui_info(display: &mut Framebuffer) -> Result<()> {
+    draw_pages(display, &[InputEvent::Left])
+}
+
+/// 示例温度数据: 缓慢上升并带有周期波动, now 秒内每秒一个读数
+fn sample_history(now: u64) -> TemperatureHistory {
+    let mut history = TemperatureHistory::default();
+    for t in 0..=now {
+        let t_min = t as f32 / 60.0;
+        history.push(t, 38.0 + t_min * 0.05 + (t_min * 1.3).sin() * 1.5);
+    }
+    history
+}
+
+/// 开机不到 5 分钟, 曲线没有占满横轴
+fn draw_chart_minutes(display: &mut Framebuffer) -> Result<()> {
+    let range = HistoryRange::Minutes5;
+    chart::draw_temperature_chart(display, &sample_history(200).points(range), range)
+}
+
+fn draw_chart_hour(display: &mut Framebuffer) -> Result<()> {
+    let range = HistoryRange::Hour;
+    chart::draw_temperature_chart(display, &sample_history(7200).points(range), range)
+}
+
+fn draw_qr_web(display: &mut Framebuffer) -> Result<()> {
+    qr::draw_qr_screen(display, "http://192.168.1.42/", "http://192.168.1.42/")
+}
+
+fn draw_qr_wifi(display: &mut Framebuffer) -> Result<()> {
+    let text = qr::wifi_join_string("esp32-lab", "secret;pass");
+    qr::draw_qr_screen(display, &text, "WiFi: esp32-lab")
+}
+
+fn sample_clock(face: clock::ClockFace) -> clock::ClockSettings {
+    clock::ClockSettings {
+        face,
+        utc_offset: 8 * 60,
+        alarms: vec![
+            clock::Alarm {
+                hour: 7,
+                minute: 30,
+            },
+            clock::Alarm {
+                hour: 21,
+                minute: 0,
+            },
+        ],
+    }
+}
+
+/// 2026-02-14 10:08:42 UTC+8
+fn sample_time() -> clock::LocalTime {
+    clock::LocalTime::from_unix(1_771_034_922, 8 * 60)
+}
+
+fn draw_clock_digital(display: &mut Framebuffer) -> Result<()> {
+    let settings = sample_clock(clock::ClockFace::Digital);
+    clock::draw_clock_screen(display, &settings, Some(sample_time()))
+}
+
+fn draw_clock_analog(display: &mut Framebuffer) -> Result<()> {
+    let settings = sample_clock(clock::ClockFace::Analog);
+    clock::draw_clock_screen(display, &settings, Some(sample_time()))
+}
+
+fn draw_clock_unsynced(display: &mut Framebuffer) -> Result<()> {
+    clock::draw_clock_screen(display, &clock::ClockSettings::default(), None)
+}
+
+/// 启动阶段和固件中的 `boot::BOOT_STAGES` 一致, boot 模块依赖 esp-idf 不能直接引用
+const BOOT_STAGES: [&str; 7] = [
+    "storage",
+    "io expander",
+    "display",
+    "wifi",
+    "peripherals",
+    "ble",
+    "http server",
+];
+
+fn draw_boot(display: &mut Framebuffer) -> Result<()> {
+    ui::draw_boot_splash(display, &BOOT_STAGES, 3)
+}
+
+fn draw_fatal(display: &mut Framebuffer) -> Result<()> {
+    ui::draw_fatal_error(
+        display,
+        "wifi",
+        "ESP_ERR_NO_MEM (error code 257) while creating wifi driver",
+        true,
+    )
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("golden")
+}
+
+fn main() -> Result<()> {
+    let mode = std::env::args().nth(1);
+    let out_dir = match mode.as_deref() {
+        None | Some("--check") => Path::new(env!("CARGO_MANIFEST_DIR")).join("target/snapshots"),
+        Some("--bless") => golden_dir(),
+        Some(arg) => return Err(anyhow!("unknown argument {arg}, use --check or --bless")),
+    };
+    fs::create_dir_all(&out_dir)?;
+
+    let mut mismatches = Vec::new();
+    for (width, height) in PANEL_SIZES {
+        for (name, scene) in SCENES {
+            let file_name = format!("{name}_{width}x{height}.png");
+            let mut display = Framebuffer::new(width, height);
+            scene(&mut display)?;
+            // 内容超出屏幕时会被截掉, 这样的快照不能作为 golden
+            if display.outside_pixels() > 0 {
+                mismatches.push(format!(
+                    "{file_name}: {} pixels drawn outside the screen",
+                    display.outside_pixels()
+                ));
+            }
+            let path = out_dir.join(&file_name);
+            display.save_png(&path)?;
+            println!("{}", path.display());
+
+            if mode.as_deref() == Some("--check") {
+                let golden = golden_dir().join(&file_name);
+                match framebuffer::load_png(&golden) {
+                    Ok((w, h, pixels)) if (w, h) == (width, height) => {
+                        let diff = pixels
+                            .chunks_exact(3)
+                            .zip(display.to_rgb8().chunks_exact(3))
+                            .filter(|(a, b)| a != b)
+                            .count();
+                        if diff > 0 {
+                            mismatches.push(format!("{file_name}: {diff} pixels differ"));
+                        }
+                    }
+                    Ok((w, h, _)) => mismatches.push(format!("{file_name}: golden is {w}x{h}")),
+                    Err(err) => mismatches.push(format!("{file_name}: {err}")),
+                }
+            }
+        }
+    }
+    if !mismatches.is_empty() {
+        return Err(anyhow!(
+            "snapshots differ from golden images:\n{}",
+            mismatches.join("\n")
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,6 @@
+//! 固件中与硬件无关的模块的测试, 每个模块一个文件
+
+mod button;
+mod font;
+mod log_screen;
+mod telemetry;
@@ -0,0 +1,146 @@
+use crate::button::{ButtonAction, ButtonEvent, Buttons, LONG_PRESS};
+use crate::widget::InputEvent;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+/// 按顺序在 (毫秒, 按键电平) 时刻采样, 返回产生的所有事件
+fn run(samples: &[(u64, u8)]) -> Vec<(u64, ButtonEvent)> {
+    let start = Instant::now();
+    let mut buttons = Buttons::new();
+    let events: Receiver<ButtonEvent> = buttons.subscribe();
+    let mut result = Vec::new();
+    for &(ms, pressed) in samples {
+        buttons.poll(pressed, start + Duration::from_millis(ms));
+        result.extend(events.try_iter().map(|event| (ms, event)));
+    }
+    result
+}
+
+/// 每 10ms 采样一次, levels 中每项为 (持续毫秒, 按键电平)
+fn run_levels(levels: &[(u64, u8)]) -> Vec<(u64, ButtonEvent)> {
+    let mut samples = Vec::new();
+    let mut ms = 0;
+    for &(duration, pressed) in levels {
+        for _ in 0..duration / 10 {
+            samples.push((ms, pressed));
+            ms += 10;
+        }
+    }
+    run(&samples)
+}
+
+fn event(button: usize, action: ButtonAction) -> ButtonEvent {
+    ButtonEvent { button, action }
+}
+
+fn actions(events: &[(u64, ButtonEvent)]) -> Vec<ButtonAction> {
+    events.iter().map(|(_, event)| event.action).collect()
+}
+
+#[test]
+fn bounce_is_ignored() {
+    let events = run_levels(&[(50, 0), (10, 1), (10, 0), (20, 1), (10, 0), (100, 0)]);
+    assert!(events.is_empty(), "{events:?}");
+}
+
+#[test]
+fn press_and_release() {
+    let events = run_levels(&[(50, 0), (200, 0b0100), (100, 0)]);
+    assert_eq!(
+        events,
+        [
+            (80, event(2, ButtonAction::Press)),
+            (
+                280,
+                event(
+                    2,
+                    ButtonAction::Release {
+                        held: Duration::from_millis(200)
+                    }
+                )
+            ),
+        ]
+    );
+    assert_eq!(events[1].1.input_event(), Some(InputEvent::Select));
+}
+
+#[test]
+fn buttons_are_independent() {
+    let events = run_levels(&[(50, 0), (100, 0b0001), (100, 0b1001), (100, 0)]);
+    let buttons: Vec<usize> = events.iter().map(|(_, event)| event.button).collect();
+    assert_eq!(buttons, [0, 3, 0, 3]);
+}
+
+#[test]
+fn long_press_once() {
+    let events = run_levels(&[(50, 0), (2000, 0b0010), (100, 0)]);
+    assert_eq!(
+        actions(&events),
+        [
+            ButtonAction::Press,
+            ButtonAction::LongPress,
+            ButtonAction::Release {
+                held: Duration::from_millis(2000)
+            },
+        ]
+    );
+    assert_eq!(events[1].0 - events[0].0, LONG_PRESS.as_millis() as u64);
+    // 长按时翻页, 松开不再产生短按
+    assert_eq!(events[1].1.input_event(), Some(InputEvent::Right));
+    assert_eq!(events[2].1.input_event(), None);
+    assert!(!events[1].1.toggles_ui());
+}
+
+#[test]
+fn long_press_back_toggles_ui() {
+    let events = run_levels(&[(50, 0), (1000, 0b1000), (100, 0)]);
+    let toggles: Vec<bool> = events.iter().map(|(_, event)| event.toggles_ui()).collect();
+    assert_eq!(toggles, [false, true, false]);
+    assert!(events
+        .iter()
+        .all(|(_, event)| event.input_event().is_none()));
+}
+
+#[test]
+fn double_click() {
+    let events = run_levels(&[(50, 0), (100, 0b1000), (200, 0), (100, 0b1000), (100, 0)]);
+    assert_eq!(
+        actions(&events),
+        [
+            ButtonAction::Press,
+            ButtonAction::Release {
+                held: Duration::from_millis(100)
+            },
+            ButtonAction::Press,
+            ButtonAction::DoubleClick,
+            ButtonAction::Release {
+                held: Duration::from_millis(100)
+            },
+        ]
+    );
+    assert!(events[3].1.sleeps_display());
+    assert!(!events[2].1.sleeps_display());
+}
+
+#[test]
+fn slow_second_click_is_not_double() {
+    let events = run_levels(&[(50, 0), (100, 0b1000), (600, 0), (100, 0b1000), (100, 0)]);
+    assert!(!actions(&events).contains(&ButtonAction::DoubleClick));
+}
+
+#[test]
+fn long_press_does_not_start_double_click() {
+    let events = run_levels(&[(50, 0), (1000, 0b0001), (100, 0), (100, 0b0001), (100, 0)]);
+    assert!(!actions(&events).contains(&ButtonAction::DoubleClick));
+}
+
+#[test]
+fn dropped_subscriber_is_removed() {
+    let mut buttons = Buttons::new();
+    let kept = buttons.subscribe();
+    drop(buttons.subscribe());
+    let start = Instant::now();
+    buttons.poll(1, start);
+    buttons.poll(1, start + Duration::from_millis(40));
+    assert_eq!(kept.try_iter().count(), 1);
+}
@@ -0,0 +1,48 @@
+use crate::font::BitmapFont;
+use crate::framebuffer::Framebuffer;
+use embedded_graphics::geometry::Point;
+use embedded_graphics::pixelcolor::{Rgb565, RgbColor};
+
+/// 行高 4, 只有一个字符 'A': 宽 3, 步进 4, 点阵为一个 3x4 的方框
+fn font_data() -> &'static [u8] {
+    let mut data = Vec::new();
+    data.extend_from_slice(b"BFNT");
+    data.extend_from_slice(&[1, 4, 0, 0]);
+    data.extend_from_slice(&1_u32.to_le_bytes());
+    data.extend_from_slice(&('A' as u32).to_le_bytes());
+    data.extend_from_slice(&20_u32.to_le_bytes());
+    data.extend_from_slice(&[3, 4, 0b1110_0000, 0b1010_0000, 0b1010_0000, 0b1110_0000]);
+    data.leak()
+}
+
+#[test]
+fn static_font_glyph() {
+    let mut font = BitmapFont::from_static(font_data()).unwrap();
+    assert_eq!(font.height(), 4);
+    let glyph = font.glyph('A').unwrap().unwrap();
+    assert_eq!((glyph.width, glyph.advance), (3, 4));
+    assert!(font.glyph('B').unwrap().is_none());
+}
+
+#[test]
+fn static_font_draw() {
+    let mut font = BitmapFont::from_static(font_data()).unwrap();
+    let mut display = Framebuffer::new(16, 8);
+    let end = font
+        .draw_text(&mut display, "AA", Point::new(1, 2), Rgb565::WHITE, None)
+        .unwrap();
+    assert_eq!(end, Point::new(9, 2));
+    assert_eq!(display.outside_pixels(), 0);
+    let lit = display.to_rgb8().chunks(3).filter(|p| p[0] == 255).count();
+    assert_eq!(lit, 20);
+}
+
+#[test]
+fn static_font_rejects_bad_data() {
+    assert!(BitmapFont::from_static(b"BFNT").is_err());
+    assert!(BitmapFont::from_static(b"XXXX\x01\x04\x00\x00\x00\x00\x00\x00").is_err());
+    // 索引指向数据末尾之后
+    let data = &font_data()[..21];
+    let mut font = BitmapFont::from_static(data).unwrap();
+    assert!(font.glyph('A').is_err());
+}
@@ -0,0 +1,49 @@
+use crate::framebuffer::Framebuffer;
+use crate::ui;
+use log::Level;
+
+fn records(texts: &[&str]) -> Vec<(Level, String)> {
+    texts
+        .iter()
+        .enumerate()
+        .map(|(i, text)| {
+            let level = if i % 2 == 0 { Level::Info } else { Level::Warn };
+            (level, text.to_string())
+        })
+        .collect()
+}
+
+/// 在已有内容上增量绘制, 结果和清屏后整屏绘制相同
+fn assert_incremental(before: &[&str], after: &[&str]) {
+    let mut incremental = Framebuffer::new(60, 40);
+    let old = ui::log_screen_lines(&incremental, &records(before));
+    ui::draw_log_lines(&mut incremental, &old, None).unwrap();
+    let new = ui::log_screen_lines(&incremental, &records(after));
+    ui::draw_log_lines(&mut incremental, &new, Some(&old)).unwrap();
+
+    let mut full = Framebuffer::new(60, 40);
+    ui::draw_log_screen(&mut full, &records(after)).unwrap();
+    assert!(incremental.to_rgb8() == full.to_rgb8());
+}
+
+#[test]
+fn only_last_screen_is_kept() {
+    // 60x40 的屏幕每行 10 个字符, 共 4 行
+    let display = Framebuffer::new(60, 40);
+    let lines = ui::log_screen_lines(&display, &records(&["a", "b", "c", "0123456789abc"]));
+    let texts: Vec<&str> = lines.iter().map(|(_, text)| text.as_str()).collect();
+    assert_eq!(texts, ["W b", "I c", "W 01234567", "89abc"]);
+}
+
+#[test]
+fn append_line() {
+    assert_incremental(&["boot", "wifi"], &["boot", "wifi", "ble"]);
+}
+
+#[test]
+fn scroll_with_shorter_lines() {
+    assert_incremental(
+        &["long line one", "long line two", "x"],
+        &["long line two", "x", "y", "z"],
+    );
+}
@@ -0,0 +1,59 @@
+use crate::telemetry::{TelemetryRecord, FLAG_FS_READY, TELEMETRY_LEN, TELEMETRY_VERSION};
+
+fn sample() -> TelemetryRecord {
+    TelemetryRecord {
+        flags: FLAG_FS_READY,
+        counter: 0x0102_0304,
+        temperature: -12.34,
+        uptime: 93_784,
+    }
+}
+
+#[test]
+fn encode_layout() {
+    let buf = sample().encode();
+    assert_eq!(buf[0], TELEMETRY_VERSION);
+    assert_eq!(buf[1], FLAG_FS_READY);
+    assert_eq!(buf[2..6], [0x04, 0x03, 0x02, 0x01]);
+    assert_eq!(i16::from_le_bytes([buf[6], buf[7]]), -1234);
+    assert_eq!(u32::from_le_bytes(buf[8..12].try_into().unwrap()), 93_784);
+}
+
+#[test]
+fn round_trip() {
+    let record = sample();
+    assert_eq!(TelemetryRecord::decode(&record.encode()).unwrap(), record);
+}
+
+#[test]
+fn temperature_is_clamped() {
+    let record = TelemetryRecord {
+        temperature: 1000.0,
+        ..sample()
+    };
+    let decoded = TelemetryRecord::decode(&record.encode()).unwrap();
+    assert_eq!(decoded.temperature, i16::MAX as f32 / 100.0);
+}
+
+#[test]
+fn longer_data_is_accepted() {
+    let mut data = sample().encode().to_vec();
+    data.extend_from_slice(&[0xaa, 0xbb]);
+    assert_eq!(TelemetryRecord::decode(&data).unwrap(), sample());
+}
+
+#[test]
+fn version_mismatch() {
+    let mut data = sample().encode();
+    data[0] = TELEMETRY_VERSION + 1;
+    let err = TelemetryRecord::decode(&data).unwrap_err();
+    assert!(err.to_string().contains("unsupported telemetry version"));
+}
+
+#[test]
+fn short_buffer() {
+    let data = sample().encode();
+    let err = TelemetryRecord::decode(&data[..TELEMETRY_LEN - 1]).unwrap_err();
+    assert!(err.to_string().contains("too short"));
+    assert!(TelemetryRecord::decode(&[]).is_err());
+}
@@ -0,0 +1,195 @@
+use crate::board::BoardEsp32State;
+use crate::console;
+use anyhow::{anyhow, Result};
+use esp32_nimble::{utilities::BleUuid, BLEAddress, BLEClient, BLEDevice, BLEScan};
+use esp_idf_svc::hal::task::block_on;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// 单次扫描时长, 单位 ms
+const SCAN_TIME_MS: i32 = 5000;
+/// 两次扫描之间的间隔
+const SCAN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// 外部传感器上报的一次读数
+#[derive(Debug, Clone)]
+pub struct ExternalSensorReading {
+    pub name: &'static str,
+    pub temperature: f32,
+    /// 收到读数时的上电时间, 单位 s
+    pub updated_at: u64,
+}
+
+/// 需要连接的外部 ble 传感器
+pub struct BleSensorConfig {
+    /// 读数显示用的名称
+    pub name: &'static str,
+    /// 广播名前缀, 用于扫描时匹配设备
+    pub adv_name_prefix: &'static str,
+    pub service: BleUuid,
+    pub characteristic: BleUuid,
+    /// 把通知数据解析成摄氏度
+    pub decode: fn(&[u8]) -> Option<f32>,
+}
+
+/// 默认支持的传感器: 标准 Environmental Sensing 和 Health Thermometer 服务
+pub fn default_sensors() -> Vec<BleSensorConfig> {
+    vec![
+        BleSensorConfig {
+            name: "ess_thermometer",
+            adv_name_prefix: "ATC_",
+            service: BleUuid::from_uuid16(0x181a),
+            characteristic: BleUuid::from_uuid16(0x2a6e),
+            decode: decode_ess_temperature,
+        },
+        BleSensorConfig {
+            name: "health_thermometer",
+            adv_name_prefix: "Thermometer",
+            service: BleUuid::from_uuid16(0x1809),
+            characteristic: BleUuid::from_uuid16(0x2a1c),
+            decode: decode_health_temperature,
+        },
+    ]
+}
+
+/// ESS Temperature(0x2A6E): sint16, 单位 0.01 摄氏度, 0x8000 表示未知
+pub fn decode_ess_temperature(data: &[u8]) -> Option<f32> {
+    let raw = i16::from_le_bytes(data.get(..2)?.try_into().ok()?);
+    if raw == i16::MIN {
+        return None;
+    }
+    Some(raw as f32 / 100.0)
+}
+
+/// Temperature Measurement(0x2A1C): flags + IEEE-11073 32bit FLOAT, flags bit0 为华氏度
+pub fn decode_health_temperature(data: &[u8]) -> Option<f32> {
+    let flags = *data.first()?;
+    let raw = u32::from_le_bytes(data.get(1..5)?.try_into().ok()?);
+    // 尾数 0x7ffffe~0x800002 是 +INF, NaN, NRes, 保留值和 -INF, 不是读数
+    if (0x7f_fffe..=0x80_0002).contains(&(raw & 0xff_ffff)) {
+        return None;
+    }
+    // 高 8 位为有符号指数, 低 24 位为有符号尾数
+    let exponent = (raw >> 24) as i8 as i32;
+    let mantissa = ((raw << 8) as i32) >> 8;
+    let value = mantissa as f32 * 10_f32.powi(exponent);
+    if flags & 0x01 != 0 {
+        Some((value - 32.0) * 5.0 / 9.0)
+    } else {
+        Some(value)
+    }
+}
+
+/// 启动 ble 主机线程, 连接配置的外部传感器并把读数写入 board 状态
+pub fn ble_central_start(
+    board: Arc<Mutex<BoardEsp32State>>,
+    sensors: Vec<BleSensorConfig>,
+) -> Result<JoinHandle<Result<()>>> {
+    let handle = thread::Builder::new()
+        .stack_size(8 * 1024)
+        .spawn(move || block_on(central_loop(board, sensors)))?;
+    Ok(handle)
+}
+
+async fn central_loop(
+    board: Arc<Mutex<BoardEsp32State>>,
+    sensors: Vec<BleSensorConfig>,
+) -> Result<()> {
+    let ble = BLEDevice::take();
+    let mut clients: Vec<Option<BLEClient>> = sensors.iter().map(|_| None).collect();
+    loop {
+        if board.lock().expect("Failed to lock board mutex").exit {
+            log::info!("ble central stopped");
+            break Ok(());
+        }
+        // 清理已经断开的传感器和它的读数, 下次扫描重新连接
+        for (client, sensor) in clients.iter_mut().zip(&sensors) {
+            if client.as_ref().is_some_and(|c| !c.connected()) {
+                log::info!("sensor {} disconnected", sensor.name);
+                *client = None;
+                board
+                    .lock()
+                    .expect("Failed to lock board mutex")
+                    .external_sensors
+                    .retain(|reading| reading.name != sensor.name);
+            }
+        }
+
+        if clients.iter().any(Option::is_none) {
+            let mut ble_scan = BLEScan::new();
+            let found = ble_scan
+                .active_scan(true)
+                .interval(100)
+                .window(99)
+                .start(ble, SCAN_TIME_MS, |device, data| {
+                    let name = data.name()?;
+                    sensors
+                        .iter()
+                        .zip(&clients)
+                        .position(|(sensor, client)| {
+                            client.is_none() && name.starts_with(sensor.adv_name_prefix)
+                        })
+                        .map(|index| (index, *device.addr()))
+                })
+                .await?;
+            if let Some((index, addr)) = found {
+                match connect_sensor(&sensors[index], addr, Arc::clone(&board)).await {
+                    Ok(client) => clients[index] = Some(client),
+                    Err(err) => {
+                        log::warn!("connect sensor {} failed: {:?}", sensors[index].name, err)
+                    }
+                }
+            }
+        }
+        thread::sleep(SCAN_INTERVAL);
+    }
+}
+
+/// 连接传感器, 找到配置的特征并订阅通知
+async fn connect_sensor(
+    sensor: &BleSensorConfig,
+    addr: BLEAddress,
+    board: Arc<Mutex<BoardEsp32State>>,
+) -> Result<BLEClient> {
+    log::info!("connecting sensor {} at {:?}", sensor.name, addr);
+    let mut client = BLEClient::new();
+    client.connect(&addr).await?;
+    let service = client.get_service(sensor.service).await?;
+    let characteristic = service.get_characteristic(sensor.characteristic).await?;
+
+    let name = sensor.name;
+    let decode = sensor.decode;
+    characteristic.on_notify(move |data| {
+        let Some(temperature) = decode(data) else {
+            log::warn!("sensor {name} sent invalid data: {:?}", data);
+            return;
+        };
+        let reading = ExternalSensorReading {
+            name,
+            temperature,
+            updated_at: console::uptime_secs(),
+        };
+        let mut board_state = board.lock().expect("Failed to lock board mutex");
+        match board_state
+            .external_sensors
+            .iter_mut()
+            .find(|r| r.name == name)
+        {
+            Some(old) => *old = reading,
+            None => board_state.external_sensors.push(reading),
+        }
+    });
+    if characteristic.can_notify() {
+        characteristic.subscribe_notify(false).await?;
+    } else if characteristic.can_indicate() {
+        characteristic.subscribe_indicate(false).await?;
+    } else {
+        return Err(anyhow!(
+            "sensor {} characteristic can not notify",
+            sensor.name
+        ));
+    }
+    log::info!("sensor {} subscribed", sensor.name);
+    Ok(client)
+}
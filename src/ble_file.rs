@@ -0,0 +1,227 @@
+//! ble 文件服务, 没有 wifi 时也能读取 `/fat` 下的日志等文件.
+//!
+//! 客户端向请求特征写入请求, 板子通过回复特征通知回复, 多字节整数均为小端:
+//!
+//! | 请求                                          | 回复                                         |
+//! |-----------------------------------------------|----------------------------------------------|
+//! | `0x01` LIST, start u16                        | status, next u16, skipped u16, `name\tsize\n`... |
+//! | `0x02` READ, offset u32, len u16, path        | status, offset u32, crc32 u32, data          |
+//! | `0x03` WRITE, offset u32, crc32 u32, path_len u8, path, data | status                        |
+//!
+//! 回复的第一个字节都是操作码, 第二个字节是状态码 `STATUS_*`.
+//! LIST 的 next 为 `LIST_END` 时表示已经列完, 否则用 next 作为 start 继续请求.
+//! 一条记录放不进一个通知, 或者文件名不能在 READ 中使用(不是 utf-8, 超过 `FS_PATH_MAX`)
+//! 的文件不列出, skipped 为这次回复跳过的条数. 记录太长时客户端需要协商更大的 mtu.
+//! WRITE 的 offset 为 0 时会新建或清空文件.
+
+use crate::storage::{self, FS_MOUNT_POINT};
+use anyhow::{anyhow, Result};
+use esp32_nimble::{uuid128, BLEServer, NimbleProperties};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+const OP_LIST: u8 = 0x01;
+const OP_READ: u8 = 0x02;
+const OP_WRITE: u8 = 0x03;
+
+const STATUS_OK: u8 = 0;
+const STATUS_BAD_REQUEST: u8 = 1;
+const STATUS_IO_ERROR: u8 = 2;
+const STATUS_CRC_MISMATCH: u8 = 3;
+const STATUS_TOO_LARGE: u8 = 4;
+
+/// LIST 已经列完
+const LIST_END: u16 = u16::MAX;
+/// 通过 ble 写入的文件最大长度, 只用于传输小文件
+const WRITE_FILE_MAX: u64 = 16 * 1024;
+/// ATT 通知的包头长度
+const ATT_NOTIFY_HEADER_LEN: usize = 3;
+
+/// 在 ble server 上添加文件服务
+pub fn file_service_start(server: &mut BLEServer) -> Result<()> {
+    let service = server.create_service(uuid128!("8d3e0001-4b5a-4c1d-9f3e-2a6c7b1f0e11"));
+    // 回复通过单独的通知特征发出
+    let response_characteristic = service.lock().create_characteristic(
+        uuid128!("8d3e0003-4b5a-4c1d-9f3e-2a6c7b1f0e11"),
+        NimbleProperties::NOTIFY,
+    );
+    let request_characteristic = service.lock().create_characteristic(
+        uuid128!("8d3e0002-4b5a-4c1d-9f3e-2a6c7b1f0e11"),
+        NimbleProperties::WRITE | NimbleProperties::WRITE_ENC | NimbleProperties::WRITE_AUTHEN,
+    );
+    request_characteristic.lock().on_write(move |args| {
+        let conn_handle = args.desc().conn_handle();
+        // 回复只能放进一个通知里
+        let max_reply = (args.desc().mtu() as usize).saturating_sub(ATT_NOTIFY_HEADER_LEN);
+        let reply = handle_request(args.recv_data(), max_reply);
+        if let Err(err) = response_characteristic
+            .lock()
+            .notify_with(&reply, conn_handle)
+        {
+            log::warn!("file service notify failed: {:?}", err);
+        }
+    });
+    Ok(())
+}
+
+/// 处理一个请求并生成回复, 回复长度不超过 max_reply
+fn handle_request(request: &[u8], max_reply: usize) -> Vec<u8> {
+    let Some((&op, body)) = request.split_first() else {
+        return vec![0, STATUS_BAD_REQUEST];
+    };
+    let result = match op {
+        OP_LIST => handle_list(body, max_reply),
+        OP_READ => handle_read(body, max_reply),
+        OP_WRITE => handle_write(body),
+        _ => Err((STATUS_BAD_REQUEST, anyhow!("unknown op {op}"))),
+    };
+    match result {
+        Ok(payload) => {
+            let mut reply = vec![op, STATUS_OK];
+            reply.extend_from_slice(&payload);
+            reply
+        }
+        Err((status, err)) => {
+            log::warn!("file service op {op} failed: {:?}", err);
+            vec![op, status]
+        }
+    }
+}
+
+type OpResult = Result<Vec<u8>, (u8, anyhow::Error)>;
+
+fn bad_request(err: impl Into<anyhow::Error>) -> (u8, anyhow::Error) {
+    (STATUS_BAD_REQUEST, err.into())
+}
+
+fn io_error(err: impl Into<anyhow::Error>) -> (u8, anyhow::Error) {
+    (STATUS_IO_ERROR, err.into())
+}
+
+fn handle_list(body: &[u8], max_reply: usize) -> OpResult {
+    let start = read_u16(body, 0).ok_or_else(|| bad_request(anyhow!("missing start")))?;
+    let mut entries = fs::read_dir(FS_MOUNT_POINT)
+        .map_err(io_error)?
+        .filter_map(|e| e.ok())
+        .map(|e| {
+            // 不是 utf-8 的文件名无法在请求中原样发回, 记为 None 跳过
+            let name = e.file_name().into_string().ok();
+            let size = e.metadata().map(|m| m.len()).unwrap_or(0);
+            (name, size)
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+
+    // 操作码和状态码 2 字节 + next 2 字节 + skipped 2 字节
+    let capacity = max_reply.saturating_sub(6);
+    let mut listing = Vec::new();
+    let mut next = LIST_END;
+    let mut skipped: u16 = 0;
+    for (index, (name, size)) in entries.iter().enumerate().skip(start as usize) {
+        // READ 时 fat_path 解析不出同一个文件的名字也不列出
+        let Some(name) = name
+            .as_deref()
+            .filter(|name| storage::fat_path(name).is_ok())
+        else {
+            skipped = skipped.saturating_add(1);
+            continue;
+        };
+        let entry = format!("{name}\t{size}\n");
+        if entry.len() > capacity {
+            // 截断的文件名不能用来读取, 跳过并告诉客户端
+            skipped = skipped.saturating_add(1);
+        } else if listing.len() + entry.len() <= capacity {
+            listing.extend_from_slice(entry.as_bytes());
+        } else {
+            next = index as u16;
+            break;
+        }
+    }
+    let mut payload = next.to_le_bytes().to_vec();
+    payload.extend_from_slice(&skipped.to_le_bytes());
+    payload.extend_from_slice(&listing);
+    Ok(payload)
+}
+
+fn handle_read(body: &[u8], max_reply: usize) -> OpResult {
+    let offset = read_u32(body, 0).ok_or_else(|| bad_request(anyhow!("missing offset")))?;
+    let len = read_u16(body, 4).ok_or_else(|| bad_request(anyhow!("missing length")))?;
+    let name = std::str::from_utf8(&body[6..]).map_err(bad_request)?;
+    let path = storage::fat_path(name).map_err(bad_request)?;
+
+    // 操作码和状态码 2 字节 + offset 4 字节 + crc 4 字节
+    let len = (len as usize).min(max_reply.saturating_sub(10));
+    let mut file = File::open(path).map_err(io_error)?;
+    file.seek(SeekFrom::Start(offset as u64))
+        .map_err(io_error)?;
+    let mut data = vec![0_u8; len];
+    let read_len = file.read(&mut data).map_err(io_error)?;
+    data.truncate(read_len);
+
+    let mut payload = offset.to_le_bytes().to_vec();
+    payload.extend_from_slice(&crc32(&data).to_le_bytes());
+    payload.extend_from_slice(&data);
+    Ok(payload)
+}
+
+fn handle_write(body: &[u8]) -> OpResult {
+    let offset = read_u32(body, 0).ok_or_else(|| bad_request(anyhow!("missing offset")))?;
+    let crc = read_u32(body, 4).ok_or_else(|| bad_request(anyhow!("missing crc")))?;
+    let path_len = *body
+        .get(8)
+        .ok_or_else(|| bad_request(anyhow!("missing path length")))? as usize;
+    let name = body
+        .get(9..9 + path_len)
+        .ok_or_else(|| bad_request(anyhow!("path truncated")))?;
+    let data = &body[9 + path_len..];
+    let path =
+        storage::fat_path(std::str::from_utf8(name).map_err(bad_request)?).map_err(bad_request)?;
+
+    if crc32(data) != crc {
+        return Err((STATUS_CRC_MISMATCH, anyhow!("crc mismatch")));
+    }
+    if offset as u64 + data.len() as u64 > WRITE_FILE_MAX {
+        return Err((
+            STATUS_TOO_LARGE,
+            anyhow!("file larger than {WRITE_FILE_MAX}"),
+        ));
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(offset == 0)
+        .open(path)
+        .map_err(io_error)?;
+    file.seek(SeekFrom::Start(offset as u64))
+        .map_err(io_error)?;
+    file.write_all(data).map_err(io_error)?;
+    Ok(Vec::new())
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// CRC-32/IEEE, 和 zlib 的 crc32 结果一致
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0_u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
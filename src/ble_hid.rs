@@ -0,0 +1,235 @@
+//! ble HID 键盘和多媒体遥控器, 让板子可以当作蓝牙小键盘使用.
+//! 按键事件来自 xl9555 的输入引脚或者控制台的 `key` 命令, 统一放到
+//! `BoardEsp32State::hid_keys` 队列中, 由发送线程依次发出.
+
+use crate::board::BoardEsp32State;
+use anyhow::Result;
+use esp32_nimble::{
+    utilities::mutex::Mutex as BleMutex, BLECharacteristic, BLEHIDDevice, BLEServer,
+};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const KEYBOARD_ID: u8 = 0x01;
+const CONSUMER_ID: u8 = 0x02;
+/// 按键队列最大长度, 超过后丢弃新的按键
+pub const HID_KEY_QUEUE_MAX: usize = 16;
+/// 按下到松开的间隔
+const KEY_PRESS_TIME: Duration = Duration::from_millis(20);
+/// 队列轮询间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// HID 报告描述符: 报告 1 为标准键盘, 报告 2 为多媒体按键
+const HID_REPORT_MAP: &[u8] = &[
+    0x05,
+    0x01, // Usage Page (Generic Desktop)
+    0x09,
+    0x06, // Usage (Keyboard)
+    0xa1,
+    0x01, // Collection (Application)
+    0x85,
+    KEYBOARD_ID, // Report ID
+    0x05,
+    0x07, //   Usage Page (Key Codes)
+    0x19,
+    0xe0, //   Usage Minimum (224)
+    0x29,
+    0xe7, //   Usage Maximum (231)
+    0x15,
+    0x00, //   Logical Minimum (0)
+    0x25,
+    0x01, //   Logical Maximum (1)
+    0x75,
+    0x01, //   Report Size (1)
+    0x95,
+    0x08, //   Report Count (8)
+    0x81,
+    0x02, //   Input (Data, Variable, Absolute) 修饰键
+    0x95,
+    0x01, //   Report Count (1)
+    0x75,
+    0x08, //   Report Size (8)
+    0x81,
+    0x01, //   Input (Constant) 保留字节
+    0x95,
+    0x06, //   Report Count (6)
+    0x75,
+    0x08, //   Report Size (8)
+    0x15,
+    0x00, //   Logical Minimum (0)
+    0x25,
+    0x73, //   Logical Maximum (115) 包含 F13~F24
+    0x05,
+    0x07, //   Usage Page (Key Codes)
+    0x19,
+    0x00, //   Usage Minimum (0)
+    0x29,
+    0x73, //   Usage Maximum (115)
+    0x81,
+    0x00, //   Input (Data, Array) 按键
+    0xc0, // End Collection
+    0x05,
+    0x0c, // Usage Page (Consumer)
+    0x09,
+    0x01, // Usage (Consumer Control)
+    0xa1,
+    0x01, // Collection (Application)
+    0x85,
+    CONSUMER_ID, // Report ID
+    0x15,
+    0x00, //   Logical Minimum (0)
+    0x26,
+    0xff,
+    0x03, //   Logical Maximum (1023)
+    0x19,
+    0x00, //   Usage Minimum (0)
+    0x2a,
+    0xff,
+    0x03, //   Usage Maximum (1023)
+    0x75,
+    0x10, //   Report Size (16)
+    0x95,
+    0x01, //   Report Count (1)
+    0x81,
+    0x00, //   Input (Data, Array)
+    0xc0, // End Collection
+];
+
+/// 一次按键
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HidKey {
+    /// 键盘按键, 修饰键位图和 HID 键码
+    Keyboard { modifiers: u8, keycode: u8 },
+    /// 多媒体按键的 HID usage
+    Consumer(u16),
+}
+
+impl HidKey {
+    /// 解析控制台里的按键名, 例如 `a`, `7`, `enter`, `f13`, `volup`
+    pub fn parse(name: &str) -> Option<Self> {
+        let key = |keycode| {
+            Some(Self::Keyboard {
+                modifiers: 0,
+                keycode,
+            })
+        };
+        match name {
+            "enter" => key(0x28),
+            "esc" => key(0x29),
+            "backspace" => key(0x2a),
+            "tab" => key(0x2b),
+            "space" => key(0x2c),
+            "volup" => Some(Self::Consumer(0x00e9)),
+            "voldown" => Some(Self::Consumer(0x00ea)),
+            "mute" => Some(Self::Consumer(0x00e2)),
+            "play" => Some(Self::Consumer(0x00cd)),
+            "next" => Some(Self::Consumer(0x00b5)),
+            "prev" => Some(Self::Consumer(0x00b6)),
+            _ => {
+                if let Some(n) = name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                    return match n {
+                        1..=12 => key(0x3a + n - 1),
+                        13..=24 => key(0x68 + n - 13),
+                        _ => None,
+                    };
+                }
+                let mut chars = name.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c @ 'a'..='z'), None) => key(0x04 + (c as u8 - b'a')),
+                    (Some('0'), None) => key(0x27),
+                    (Some(c @ '1'..='9'), None) => key(0x1e + (c as u8 - b'1')),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+/// 宏键盘按键, 对应 xl9555 的 4 个输入引脚 P14~P17
+pub const BUTTON_KEYS: [HidKey; 4] = [
+    HidKey::Keyboard {
+        modifiers: 0,
+        keycode: 0x68,
+    },
+    HidKey::Keyboard {
+        modifiers: 0,
+        keycode: 0x69,
+    },
+    HidKey::Keyboard {
+        modifiers: 0,
+        keycode: 0x6a,
+    },
+    HidKey::Keyboard {
+        modifiers: 0,
+        keycode: 0x6b,
+    },
+];
+
+/// 把按键放入发送队列
+pub fn push_key(board_state: &mut BoardEsp32State, key: HidKey) {
+    if board_state.hid_keys.len() < HID_KEY_QUEUE_MAX {
+        board_state.hid_keys.push_back(key);
+    } else {
+        log::warn!("hid key queue full, drop {:?}", key);
+    }
+}
+
+/// 在 ble server 上添加 HID 服务, 并启动按键发送线程
+pub fn hid_service_start(
+    server: &mut BLEServer,
+    board: Arc<Mutex<BoardEsp32State>>,
+) -> Result<JoinHandle<Result<()>>> {
+    let mut hid = BLEHIDDevice::new(server);
+    let keyboard_report = hid.input_report(KEYBOARD_ID);
+    let consumer_report = hid.input_report(CONSUMER_ID);
+    hid.manufacturer("TOTHTOT");
+    hid.pnp(0x02, 0x303a, 0x4001, 0x0100);
+    hid.hid_info(0x00, 0x01);
+    hid.report_map(HID_REPORT_MAP);
+    hid.set_battery_level(100);
+
+    let handle = thread::spawn(move || -> Result<()> {
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let key = {
+                let mut board_state = board.lock().expect("Failed to lock board mutex");
+                if board_state.exit {
+                    log::info!("ble hid stopped");
+                    break Ok(());
+                }
+                board_state.hid_keys.pop_front()
+            };
+            if let Some(key) = key {
+                send_key(&keyboard_report, &consumer_report, key);
+            }
+        }
+    });
+    Ok(handle)
+}
+
+/// 发送按下和松开两个报告
+fn send_key(
+    keyboard_report: &Arc<BleMutex<BLECharacteristic>>,
+    consumer_report: &Arc<BleMutex<BLECharacteristic>>,
+    key: HidKey,
+) {
+    match key {
+        HidKey::Keyboard { modifiers, keycode } => {
+            keyboard_report
+                .lock()
+                .set_value(&[modifiers, 0, keycode, 0, 0, 0, 0, 0])
+                .notify();
+            thread::sleep(KEY_PRESS_TIME);
+            keyboard_report.lock().set_value(&[0; 8]).notify();
+        }
+        HidKey::Consumer(usage) => {
+            consumer_report
+                .lock()
+                .set_value(&usage.to_le_bytes())
+                .notify();
+            thread::sleep(KEY_PRESS_TIME);
+            consumer_report.lock().set_value(&[0; 2]).notify();
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! ble 固件升级(DFU)服务, 没有 wifi 的设备也能升级固件.
+//!
+//! 控制特征(写入)的请求, 回复通过状态特征通知, 多字节整数均为小端:
+//!
+//! | 请求                                     | 回复                                   |
+//! |------------------------------------------|----------------------------------------|
+//! | `0x01` START, image_size u32, sha256 [32] | status, next_seq u32, received u32     |
+//! | `0x02` STATUS                            | status, next_seq u32, received u32     |
+//! | `0x03` FINISH                            | status                                 |
+//! | `0x04` ABORT                             | status                                 |
+//!
+//! 数据特征(无响应写入)的格式为 seq u32 + 固件数据, seq 从 0 开始递增.
+//! seq 不连续时回复 `STATUS_SEQ_ERROR` 以及期望的 seq, 客户端从该 seq 重新发送.
+//! START 需要擦除 ota 分区, 擦除耗时几秒, 在单独的线程中进行: START 先回复 `STATUS_ERASING`,
+//! 擦除完成后再通知一次 START 的回复, 擦除期间的其他请求和数据都回复 `STATUS_ERASING`.
+//! 断开连接不会丢弃升级进度, 重新连接后发送相同的 START 即可从断点继续.
+//! 进度只保存在内存中, 板子重启后需要从头开始升级.
+//! FINISH 时校验长度和 SHA-256, 通过后切换启动分区并重启,
+//! 新固件启动完成后调用 `mark_app_valid`, 开启回滚时没有调用的固件会在下次重启时回滚.
+
+use anyhow::{anyhow, Result};
+use esp32_nimble::{
+    utilities::mutex::Mutex as BleMutex, uuid128, BLECharacteristic, BLEServer, NimbleProperties,
+};
+use esp_idf_svc::sys::{
+    self, esp, esp_ota_abort, esp_ota_begin, esp_ota_end, esp_ota_get_next_update_partition,
+    esp_ota_handle_t, esp_ota_mark_app_valid_cancel_rollback, esp_ota_set_boot_partition,
+    esp_ota_write,
+};
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const OP_START: u8 = 0x01;
+const OP_STATUS: u8 = 0x02;
+const OP_FINISH: u8 = 0x03;
+const OP_ABORT: u8 = 0x04;
+/// 数据特征的回复操作码
+const OP_DATA: u8 = 0x10;
+
+const STATUS_OK: u8 = 0;
+const STATUS_BAD_REQUEST: u8 = 1;
+const STATUS_NOT_STARTED: u8 = 2;
+const STATUS_SEQ_ERROR: u8 = 3;
+const STATUS_FLASH_ERROR: u8 = 4;
+const STATUS_VERIFY_FAILED: u8 = 5;
+const STATUS_ERASING: u8 = 6;
+
+/// 升级服务的状态, 控制和数据特征共用
+#[derive(Default)]
+struct OtaState {
+    session: Option<OtaSession>,
+    /// 工作线程正在擦除分区
+    erasing: bool,
+}
+
+/// 控制请求处理完成后的动作
+enum ControlReply {
+    /// 直接回复
+    Done(Vec<u8>),
+    /// 需要在工作线程中擦除分区并开始新的升级
+    Begin { image_size: u32, sha256: [u8; 32] },
+}
+
+/// 一次升级的进度
+struct OtaSession {
+    handle: esp_ota_handle_t,
+    image_size: u32,
+    expected_sha256: [u8; 32],
+    hasher: Sha256,
+    next_seq: u32,
+    received: u32,
+}
+
+impl OtaSession {
+    /// 擦除空闲的 ota 分区并开始写入
+    fn begin(image_size: u32, expected_sha256: [u8; 32]) -> Result<Self> {
+        let partition = unsafe { esp_ota_get_next_update_partition(core::ptr::null()) };
+        if partition.is_null() {
+            return Err(anyhow!("no ota partition"));
+        }
+        let mut handle: esp_ota_handle_t = 0;
+        esp!(unsafe { esp_ota_begin(partition, image_size as usize, &mut handle) })?;
+        log::info!("ota begin, image size: {image_size}");
+        Ok(Self {
+            handle,
+            image_size,
+            expected_sha256,
+            hasher: Sha256::new(),
+            next_seq: 0,
+            received: 0,
+        })
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        if self.received as usize + data.len() > self.image_size as usize {
+            return Err(anyhow!("image larger than {}", self.image_size));
+        }
+        esp!(unsafe { esp_ota_write(self.handle, data.as_ptr() as *const _, data.len()) })?;
+        self.hasher.update(data);
+        self.received += data.len() as u32;
+        self.next_seq += 1;
+        Ok(())
+    }
+
+    /// 校验长度和 SHA-256, 通过后设置启动分区
+    fn finish(self) -> Result<(), (u8, anyhow::Error)> {
+        if self.received != self.image_size {
+            let err = anyhow!("received {} of {} bytes", self.received, self.image_size);
+            self.abort();
+            return Err((STATUS_VERIFY_FAILED, err));
+        }
+        let sha256: [u8; 32] = self.hasher.clone().finalize().into();
+        if sha256 != self.expected_sha256 {
+            self.abort();
+            return Err((STATUS_VERIFY_FAILED, anyhow!("sha256 mismatch")));
+        }
+        esp!(unsafe { esp_ota_end(self.handle) }).map_err(|e| (STATUS_VERIFY_FAILED, e.into()))?;
+        let partition = unsafe { esp_ota_get_next_update_partition(core::ptr::null()) };
+        esp!(unsafe { esp_ota_set_boot_partition(partition) })
+            .map_err(|e| (STATUS_FLASH_ERROR, e.into()))?;
+        Ok(())
+    }
+
+    fn abort(self) {
+        if let Err(err) = esp!(unsafe { esp_ota_abort(self.handle) }) {
+            log::warn!("ota abort failed: {:?}", err);
+        }
+    }
+
+    fn progress(&self) -> Vec<u8> {
+        let mut payload = self.next_seq.to_le_bytes().to_vec();
+        payload.extend_from_slice(&self.received.to_le_bytes());
+        payload
+    }
+}
+
+/// 在 ble server 上添加固件升级服务
+pub fn ota_service_start(server: &mut BLEServer) -> Result<()> {
+    let service = server.create_service(uuid128!("5e1f0001-7c3a-4d2b-8e6f-9a0b1c2d3e4f"));
+    let status_characteristic = service.lock().create_characteristic(
+        uuid128!("5e1f0002-7c3a-4d2b-8e6f-9a0b1c2d3e4f"),
+        NimbleProperties::NOTIFY,
+    );
+    let control_characteristic = service.lock().create_characteristic(
+        uuid128!("5e1f0003-7c3a-4d2b-8e6f-9a0b1c2d3e4f"),
+        NimbleProperties::WRITE | NimbleProperties::WRITE_ENC | NimbleProperties::WRITE_AUTHEN,
+    );
+    let data_characteristic = service.lock().create_characteristic(
+        uuid128!("5e1f0004-7c3a-4d2b-8e6f-9a0b1c2d3e4f"),
+        NimbleProperties::WRITE_NO_RSP
+            | NimbleProperties::WRITE_ENC
+            | NimbleProperties::WRITE_AUTHEN,
+    );
+
+    let state: Arc<Mutex<OtaState>> = Arc::new(Mutex::new(OtaState::default()));
+
+    let control_state = Arc::clone(&state);
+    let control_status = Arc::clone(&status_characteristic);
+    control_characteristic.lock().on_write(move |args| {
+        let request = args.recv_data();
+        let op = request.first().copied().unwrap_or(0);
+        let conn_handle = args.desc().conn_handle();
+        let mut state = control_state.lock().expect("Failed to lock ota state");
+        let reply = match handle_control(&mut state, request) {
+            Ok(ControlReply::Done(payload)) => [&[op, STATUS_OK][..], &payload[..]].concat(),
+            Ok(ControlReply::Begin { image_size, sha256 }) => {
+                let spawned = begin_in_background(
+                    Arc::clone(&control_state),
+                    Arc::clone(&control_status),
+                    conn_handle,
+                    image_size,
+                    sha256,
+                );
+                match spawned {
+                    Ok(_) => vec![op, STATUS_ERASING],
+                    Err(err) => {
+                        log::warn!("ota begin failed: {:?}", err);
+                        state.erasing = false;
+                        vec![op, STATUS_FLASH_ERROR]
+                    }
+                }
+            }
+            Err((status, err)) => {
+                log::warn!("ota op {op} failed: {:?}", err);
+                vec![op, status]
+            }
+        };
+        drop(state);
+        if let Err(err) = control_status.lock().notify_with(&reply, conn_handle) {
+            log::warn!("ota notify failed: {:?}", err);
+        }
+        if op == OP_FINISH && reply[1] == STATUS_OK {
+            log::info!("ota finished, restarting");
+            thread::spawn(|| {
+                thread::sleep(Duration::from_millis(1000));
+                unsafe { sys::esp_restart() };
+            });
+        }
+    });
+
+    data_characteristic.lock().on_write(move |args| {
+        let mut state = state.lock().expect("Failed to lock ota state");
+        let status = match handle_data(&mut state, args.recv_data()) {
+            Ok(()) => return,
+            Err((status, err)) => {
+                log::warn!("ota data failed: {:?}", err);
+                status
+            }
+        };
+        // 写入失败时告诉客户端当前期望的 seq
+        let mut reply = vec![OP_DATA, status];
+        if let Some(session) = state.session.as_ref() {
+            reply.extend_from_slice(&session.progress());
+        }
+        if let Err(err) = status_characteristic
+            .lock()
+            .notify_with(&reply, args.desc().conn_handle())
+        {
+            log::warn!("ota notify failed: {:?}", err);
+        }
+    });
+    Ok(())
+}
+
+/// 擦除分区会阻塞几秒, 不能在 ble 回调中进行, 在工作线程中开始升级, 完成后通知 START 的回复
+fn begin_in_background(
+    state: Arc<Mutex<OtaState>>,
+    status_characteristic: Arc<BleMutex<BLECharacteristic>>,
+    conn_handle: u16,
+    image_size: u32,
+    sha256: [u8; 32],
+) -> std::io::Result<thread::JoinHandle<()>> {
+    thread::Builder::new().stack_size(4 * 1024).spawn(move || {
+        let begin = OtaSession::begin(image_size, sha256);
+        let mut state = state.lock().expect("Failed to lock ota state");
+        state.erasing = false;
+        let reply = match begin {
+            Ok(session) => {
+                let reply = [&[OP_START, STATUS_OK][..], &session.progress()].concat();
+                state.session = Some(session);
+                reply
+            }
+            Err(err) => {
+                log::warn!("ota begin failed: {:?}", err);
+                vec![OP_START, STATUS_FLASH_ERROR]
+            }
+        };
+        drop(state);
+        if let Err(err) = status_characteristic
+            .lock()
+            .notify_with(&reply, conn_handle)
+        {
+            log::warn!("ota notify failed: {:?}", err);
+        }
+    })
+}
+
+fn handle_control(
+    state: &mut OtaState,
+    request: &[u8],
+) -> Result<ControlReply, (u8, anyhow::Error)> {
+    if state.erasing {
+        return Err((STATUS_ERASING, anyhow!("ota partition is being erased")));
+    }
+    let session = &mut state.session;
+    match request.first().copied() {
+        Some(OP_START) => {
+            let image_size = request
+                .get(1..5)
+                .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+                .ok_or((STATUS_BAD_REQUEST, anyhow!("missing image size")))?;
+            let sha256: [u8; 32] = request
+                .get(5..37)
+                .and_then(|b| b.try_into().ok())
+                .ok_or((STATUS_BAD_REQUEST, anyhow!("missing sha256")))?;
+            // 相同的固件继续上次的进度, 否则重新开始
+            match session.take() {
+                Some(old) if old.image_size == image_size && old.expected_sha256 == sha256 => {
+                    log::info!("ota resume at seq {}", old.next_seq);
+                    let progress = old.progress();
+                    *session = Some(old);
+                    Ok(ControlReply::Done(progress))
+                }
+                old => {
+                    if let Some(old) = old {
+                        old.abort();
+                    }
+                    state.erasing = true;
+                    Ok(ControlReply::Begin { image_size, sha256 })
+                }
+            }
+        }
+        Some(OP_STATUS) => session
+            .as_ref()
+            .map(|session| ControlReply::Done(session.progress()))
+            .ok_or((STATUS_NOT_STARTED, anyhow!("ota not started"))),
+        Some(OP_FINISH) => {
+            let current = session
+                .take()
+                .ok_or((STATUS_NOT_STARTED, anyhow!("ota not started")))?;
+            current.finish()?;
+            Ok(ControlReply::Done(Vec::new()))
+        }
+        Some(OP_ABORT) => {
+            if let Some(current) = session.take() {
+                current.abort();
+            }
+            Ok(ControlReply::Done(Vec::new()))
+        }
+        _ => Err((STATUS_BAD_REQUEST, anyhow!("unknown ota request"))),
+    }
+}
+
+fn handle_data(state: &mut OtaState, request: &[u8]) -> Result<(), (u8, anyhow::Error)> {
+    if state.erasing {
+        return Err((STATUS_ERASING, anyhow!("ota partition is being erased")));
+    }
+    let current = state
+        .session
+        .as_mut()
+        .ok_or((STATUS_NOT_STARTED, anyhow!("ota not started")))?;
+    let seq = request
+        .get(..4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or((STATUS_BAD_REQUEST, anyhow!("missing seq")))?;
+    if seq < current.next_seq {
+        // 重传的数据块已经写过了
+        return Ok(());
+    }
+    if seq > current.next_seq {
+        return Err((
+            STATUS_SEQ_ERROR,
+            anyhow!("expect seq {}, got {seq}", current.next_seq),
+        ));
+    }
+    current
+        .write(&request[4..])
+        .map_err(|e| (STATUS_FLASH_ERROR, e))
+}
+
+/// 启动完成后确认当前固件可用, 开启回滚时取消回滚, 没有开启时不做任何事
+pub fn mark_app_valid() {
+    if let Err(err) = esp!(unsafe { esp_ota_mark_app_valid_cancel_rollback() }) {
+        log::warn!("ota mark app valid failed: {:?}", err);
+    }
+}
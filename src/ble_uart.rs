@@ -0,0 +1,68 @@
+use crate::board::BoardEsp32State;
+use crate::console::{self, LineBuffer};
+use anyhow::Result;
+use esp32_nimble::{uuid128, BLEServer, NimbleProperties};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// ATT 通知的包头长度, 单包有效数据为 mtu - 3
+const ATT_NOTIFY_HEADER_LEN: u16 = 3;
+/// 协议规定的最小 mtu
+const ATT_MIN_MTU: u16 = 23;
+
+/// 每个连接的行缓存, 断开连接时需要清理
+pub type NusSessions = Arc<Mutex<HashMap<u16, LineBuffer>>>;
+
+/// 在 ble server 上添加 Nordic UART Service(NUS), 兼容手机上常见的串口工具.
+/// 收到的每一行交给控制台执行, 结果按协商后的 mtu 分包通知回去
+pub fn nus_service_start(
+    server: &mut BLEServer,
+    board: Arc<Mutex<BoardEsp32State>>,
+) -> Result<NusSessions> {
+    let service = server.create_service(uuid128!("6e400001-b5a3-f393-e0a9-e50e24dcca9e"));
+    // 板子 -> 手机
+    let tx_characteristic = service.lock().create_characteristic(
+        uuid128!("6e400003-b5a3-f393-e0a9-e50e24dcca9e"),
+        NimbleProperties::NOTIFY,
+    );
+    // 手机 -> 板子
+    let rx_characteristic = service.lock().create_characteristic(
+        uuid128!("6e400002-b5a3-f393-e0a9-e50e24dcca9e"),
+        NimbleProperties::WRITE
+            | NimbleProperties::WRITE_NO_RSP
+            | NimbleProperties::WRITE_ENC
+            | NimbleProperties::WRITE_AUTHEN,
+    );
+
+    // 每个连接单独组包, 避免多个手机同时发送时数据混在一起
+    let sessions: NusSessions = Arc::new(Mutex::new(HashMap::new()));
+    let rx_sessions = Arc::clone(&sessions);
+    rx_characteristic.lock().on_write(move |args| {
+        let conn_handle = args.desc().conn_handle();
+        let mtu = args.desc().mtu();
+        let lines = rx_sessions
+            .lock()
+            .expect("Failed to lock nus sessions")
+            .entry(conn_handle)
+            .or_default()
+            .push(args.recv_data());
+        for line in lines {
+            log::info!("nus[{conn_handle}] > {line}");
+            let reply = console::execute(&line, &board);
+            let mut tx = tx_characteristic.lock();
+            for chunk in reply.as_bytes().chunks(notify_payload_len(mtu)) {
+                if let Err(err) = tx.notify_with(chunk, conn_handle) {
+                    log::warn!("nus notify failed: {:?}", err);
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(sessions)
+}
+
+/// 根据协商后的 mtu 计算单个通知能携带的数据长度
+fn notify_payload_len(mtu: u16) -> usize {
+    (mtu.max(ATT_MIN_MTU) - ATT_NOTIFY_HEADER_LEN) as usize
+}
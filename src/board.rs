@@ -2,22 +2,43 @@
 use anyhow::{anyhow, Result};
 
 // 显示屏相关
-#[cfg(feature = "use_st7789")]
+use crate::boot;
+use crate::canvas::DisplayCommand;
+use crate::clock::ClockSettings;
+use crate::dashboard::DashboardData;
+#[cfg(feature = "display")]
 use crate::display;
+use crate::display::{DisplayScreen, PanelConfig};
+use crate::history::{HistoryRange, TemperatureHistory};
+use crate::qr::QrContent;
+use crate::screen_power::{PowerRequest, DEFAULT_SCREEN_TIMEOUT};
+#[cfg(feature = "display")]
+use crate::shadow::ShadowDisplay;
+use crate::storage;
+#[cfg(feature = "display")]
+use crate::ui;
+use crate::widget::InputEvent;
 // 嵌入式服务与协议
 use core::cell::RefCell;
 // 标准库
-#[cfg(feature = "use_st7789")]
+#[cfg(feature = "display")]
 use embedded_hal_bus::spi::{ExclusiveDevice, NoDelay};
 use embedded_svc::wifi;
 // BLE相关
+use crate::ble_central::ExternalSensorReading;
+use crate::ble_hid::HidKey;
+use crate::{ble_file, ble_ota, ble_uart};
+use crate::{console, telemetry};
 use esp32_nimble::{
+    enums::{AuthReq, SecurityIOCap},
     uuid128, BLEAdvertisedDevice, BLEAdvertisementData, BLEDevice, BLEScan, NimbleProperties,
 };
 // ESP-IDF核心服务与硬件抽象
 use esp_idf_svc::hal::i2c::{I2cConfig, I2cDriver};
+#[cfg(feature = "use_psram_framebuffer")]
+use esp_idf_svc::hal::spi::{Dma, SpiDriverConfig};
 use esp_idf_svc::{
-    eventloop::EspSystemEventLoop,
+    eventloop::{EspSubscription, EspSystemEventLoop, System},
     hal::{
         gpio::{Gpio0, Gpio13, Gpio21, Output, PinDriver},
         prelude::*,
@@ -31,13 +52,15 @@ use esp_idf_svc::{
         esp, esp_vfs_fat_mount_config_t, esp_vfs_fat_spiflash_mount, nvs_flash_erase,
         nvs_flash_init, wl_handle_t, ESP_ERR_NVS_NEW_VERSION_FOUND, ESP_ERR_NVS_NO_FREE_PAGES,
     },
-    wifi::{AuthMethod, EspWifi},
+    wifi::{AuthMethod, EspWifi, WifiEvent},
 };
-#[cfg(feature = "use_st7789")]
+#[cfg(feature = "display")]
 use mipidsi::interface::SpiInterface;
+#[cfg(all(feature = "use_st7735", not(feature = "use_st7789")))]
+use mipidsi::models::ST7735s;
 #[cfg(feature = "use_st7789")]
 use mipidsi::models::ST7789;
-#[cfg(feature = "use_st7789")]
+#[cfg(feature = "display")]
 use mipidsi::NoResetPin;
 // WS2812 LED驱动
 #[cfg(feature = "use_ws2812")]
@@ -47,9 +70,12 @@ use smart_leds::{
 };
 use std::rc::Rc;
 use std::{
+    collections::VecDeque,
     ffi::CString,
     fs::{File, OpenOptions},
     io::{Read as StdRead, Write as StdWrite},
+    net::Ipv4Addr,
+    path::PathBuf,
     sync::{Arc, Mutex},
     thread::{self, JoinHandle},
     time::Duration,
@@ -61,48 +87,241 @@ use xl9555::driver::XL9555;
 /// 默认连接的wifi
 const WIFI_SSID: &str = "esp32_2.4G";
 const WIFI_PASSWD: &str = "12345678..";
+/// wifi 断开后第一次重连前的等待时间, 之后每次失败加倍
+const WIFI_RECONNECT_MIN: Duration = Duration::from_secs(1);
+/// wifi 重连的最长等待时间
+const WIFI_RECONNECT_MAX: Duration = Duration::from_secs(60);
+/// ble 期望协商的 mtu
+const BLE_PREFERRED_MTU: u16 = 247;
+/// ble 广播的键盘外观
+#[cfg(feature = "enable_ble_hid")]
+const BLE_APPEARANCE_KEYBOARD: u16 = 0x03c1;
+/// ble 遥测数据默认通知间隔
+const DEFAULT_TELEMETRY_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// 检查屏幕参数是否适合当前的屏幕型号, 控制台修改参数时先检查再交给主循环
+#[cfg(feature = "display")]
+pub fn check_display_panel(panel: &PanelConfig) -> Result<()> {
+    panel.check::<DisplayModel>()
+}
+
+#[cfg(not(feature = "display"))]
+pub fn check_display_panel(_panel: &PanelConfig) -> Result<()> {
+    Err(anyhow!("display disabled"))
+}
 
 /// 屏幕引脚定义
-#[cfg(feature = "use_st7789")]
+#[cfg(feature = "display")]
 type CsPin<'d> = PinDriver<'d, Gpio21, Output>;
-#[cfg(feature = "use_st7789")]
+#[cfg(feature = "display")]
 type DcPin<'d> = PinDriver<'d, Gpio13, Output>;
 #[allow(dead_code)]
 type Xl9555Pin<'d> = xl9555::io::Output<'d, I2cDriver<'d>>;
-#[cfg(feature = "use_st7789")]
-type MyDisplay<'d> = mipidsi::Display<
-    SpiInterface<
-        'd,
-        ExclusiveDevice<SpiBusDriver<'d, SpiDriver<'d>>, CsPin<'d>, NoDelay>,
-        DcPin<'d>,
+#[cfg(feature = "display")]
+type MyDisplay<'d> = ShadowDisplay<
+    mipidsi::Display<
+        SpiInterface<
+            'd,
+            ExclusiveDevice<SpiBusDriver<'d, SpiDriver<'d>>, CsPin<'d>, NoDelay>,
+            DcPin<'d>,
+        >,
+        DisplayModel,
+        NoResetPin,
     >,
-    DisplayModel,
-    NoResetPin,
 >;
 
+// 屏幕和帧缓存都需要选择屏幕型号, 否则 DisplayModel 和 DISPLAY_PANEL 没有定义
+#[cfg(all(
+    feature = "display",
+    not(any(feature = "use_st7789", feature = "use_st7735"))
+))]
+compile_error!("features `display` and `use_psram_framebuffer` need `use_st7789` or `use_st7735`");
+// 没有 psram 时帧缓存会占用内部内存, 构建时需要加上 sdkconfig.defaults.psram, 见 readme
+#[cfg(all(feature = "use_psram_framebuffer", not(esp_idf_spiram)))]
+compile_error!(
+    "`use_psram_framebuffer` needs CONFIG_SPIRAM: build with \
+     ESP_IDF_SDKCONFIG_DEFAULTS=\"sdkconfig.defaults;sdkconfig.defaults.psram\""
+);
+
+/// 屏幕型号由 feature 选择, 同一份固件可以用在不同尺寸屏幕的板子上.
+/// 两个都打开时(例如 `--all-features`)使用 ST7789
 #[cfg(feature = "use_st7789")]
 type DisplayModel = ST7789;
+#[cfg(feature = "use_st7789")]
+const DISPLAY_MODEL: DisplayModel = ST7789;
+#[cfg(feature = "use_st7789")]
+const DISPLAY_PANEL: display::PanelConfig = display::PanelConfig::ST7789_240X320;
+#[cfg(all(feature = "use_st7735", not(feature = "use_st7789")))]
+type DisplayModel = ST7735s;
+#[cfg(all(feature = "use_st7735", not(feature = "use_st7789")))]
+const DISPLAY_MODEL: DisplayModel = ST7735s;
+#[cfg(all(feature = "use_st7735", not(feature = "use_st7789")))]
+const DISPLAY_PANEL: display::PanelConfig = display::PanelConfig::ST7735_128X160;
+/// 屏幕背光和复位使用的 xl9555 引脚
+#[cfg(feature = "display")]
+const DISPLAY_BACKLIGHT_PIN: xl9555::Pin = xl9555::Pin::P13;
+#[cfg(feature = "display")]
+const DISPLAY_RST_PIN: xl9555::Pin = xl9555::Pin::P12;
+/// 按键所在的 xl9555 输入引脚 P14~P17, 低电平表示按下
+const BUTTON_PINS_MASK: u16 = 0b1111_0000_0000_0000;
+/// 帧缓存刷新时每次 dma 传输的最大字节数
+#[cfg(feature = "use_psram_framebuffer")]
+pub const DISPLAY_DMA_BUFFER_SIZE: usize = 4096;
 pub struct BspEsp32S3CoreBoard<'d> {
     #[cfg(feature = "use_ws2812")]
     pub ws2812: Ws2812Esp32Rmt<'d>,
     pub wifi: EspWifi<'d>,
+    /// 订阅 wifi 事件使用
+    sysloop: EspSystemEventLoop,
     mcu_temperature: TempSensorDriver<'d>,
     fs_init: bool, // 标记文件系统是否初始化成功
     wifi_ssid: String,
     wifi_password: String,
-    #[cfg(feature = "use_st7789")]
+    #[cfg(feature = "display")]
     display_rst_pin: xl9555::Pin,
-    #[cfg(feature = "use_st7789")]
+    #[cfg(feature = "display")]
     display_backlight_pin: xl9555::Pin,
-    #[cfg(feature = "use_st7789")]
+    #[cfg(feature = "display")]
     pub display: Option<MyDisplay<'d>>,
+    #[cfg(feature = "display")]
+    display_panel: display::PanelConfig,
     pub xl9555: Rc<RefCell<XL9555<I2cDriver<'d>>>>,
 }
 
-#[derive(Default, Debug)]
+#[derive(Debug)]
 pub struct BoardEsp32State {
     pub exit: bool,
     pub current_mcu_temperature: f32,
+    pub fs_ready: bool,
+    /// ble 遥测数据的通知间隔
+    pub telemetry_interval: Duration,
+    /// 当前 ble 连接数
+    pub ble_connections: usize,
+    /// 正在配对时需要在屏幕上显示的配对码, 配对结束后清空
+    pub ble_pairing_passkey: Option<u32>,
+    /// 通过 ble 主机连接的外部传感器读数
+    pub external_sensors: Vec<ExternalSensorReading>,
+    /// 等待通过 ble HID 发送的按键
+    pub hid_keys: VecDeque<HidKey>,
+    /// 期望的屏幕参数, 和当前参数不同时由主循环重新配置屏幕
+    pub display_config: Option<PanelConfig>,
+    /// 当前显示的画面
+    pub display_screen: DisplayScreen,
+    /// 图片画面显示的 fat 中的图片
+    pub display_image: Option<PathBuf>,
+    /// 请求整屏重绘, 处理后由主循环清除
+    pub display_refresh: bool,
+    /// 等待主循环执行的远程绘制命令
+    pub display_commands: VecDeque<DisplayCommand>,
+    /// 等待多页面界面处理的输入事件
+    pub ui_events: VecDeque<InputEvent>,
+    /// 进入多页面界面前的画面, 退出界面时切换回去
+    pub screen_before_ui: DisplayScreen,
+    /// 芯片温度的历史记录, 由主循环每次读取温度后写入
+    pub temperature_history: TemperatureHistory,
+    /// 温度曲线显示的时间范围
+    pub chart_range: HistoryRange,
+    /// 二维码画面显示的内容
+    pub display_qr: QrContent,
+    /// 无操作多长时间后关闭屏幕, None 表示不关闭
+    pub display_timeout: Option<Duration>,
+    /// 唤醒或关闭屏幕的请求, 处理后由主循环清除
+    pub display_power: Option<PowerRequest>,
+    /// 时钟画面的表盘样式, 时区和闹钟
+    pub clock: ClockSettings,
+}
+
+impl Default for BoardEsp32State {
+    fn default() -> Self {
+        Self {
+            exit: false,
+            current_mcu_temperature: 0.0,
+            fs_ready: false,
+            telemetry_interval: DEFAULT_TELEMETRY_INTERVAL,
+            ble_connections: 0,
+            ble_pairing_passkey: None,
+            external_sensors: Vec::new(),
+            hid_keys: VecDeque::new(),
+            display_config: None,
+            display_screen: DisplayScreen::default(),
+            display_image: None,
+            display_refresh: false,
+            display_commands: VecDeque::new(),
+            ui_events: VecDeque::new(),
+            screen_before_ui: DisplayScreen::default(),
+            temperature_history: TemperatureHistory::default(),
+            chart_range: HistoryRange::default(),
+            display_qr: QrContent::default(),
+            display_timeout: Some(DEFAULT_SCREEN_TIMEOUT),
+            display_power: None,
+            clock: ClockSettings::default(),
+        }
+    }
+}
+
+/// 显示当前的启动阶段
+#[cfg(feature = "display")]
+fn show_boot_stage(display: &mut Option<MyDisplay>) {
+    let Some(display) = display.as_mut() else {
+        return;
+    };
+    let current = boot::stage_index(boot::stage());
+    let result = ui::draw_boot_splash(display, &boot::BOOT_STAGES, current).and_then(|_| {
+        display
+            .flush()
+            .map_err(|e| anyhow!("display flush failed: {:?}", e))
+    });
+    if let Err(err) = result {
+        log::warn!("draw boot splash failed: {:?}", err);
+    }
+}
+
+/// 显示致命错误画面, 绘制失败时只记录日志
+#[cfg(feature = "display")]
+fn show_fatal_error(display: &mut MyDisplay, stage: &str, message: &str, restarting: bool) {
+    let result = ui::draw_fatal_error(display, stage, message, restarting).and_then(|_| {
+        display
+            .flush()
+            .map_err(|e| anyhow!("display flush failed: {:?}", e))
+    });
+    if let Err(err) = result {
+        log::warn!("draw fatal error failed: {:?}", err);
+    }
+}
+
+impl BoardEsp32State {
+    /// 根据当前状态生成遥测标志位
+    pub fn telemetry_flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.fs_ready {
+            flags |= telemetry::FLAG_FS_READY;
+        }
+        if self.ble_pairing_passkey.is_some() {
+            flags |= telemetry::FLAG_BLE_PAIRING;
+        }
+        if !self.external_sensors.is_empty() {
+            flags |= telemetry::FLAG_EXTERNAL_SENSOR;
+        }
+        flags
+    }
+
+    /// 加入界面输入事件, 多页面界面和温度曲线之外的画面不处理输入, 事件被丢弃,
+    /// 画面内容保持不变
+    pub fn push_ui_event(&mut self, event: InputEvent) {
+        if self.display_screen.takes_input() {
+            self.ui_events.push_back(event);
+        }
+    }
+
+    /// 进入多页面界面, 已经在界面中时回到进入前的画面
+    pub fn toggle_ui(&mut self) {
+        if self.display_screen == DisplayScreen::Ui {
+            self.display_screen = self.screen_before_ui;
+        } else {
+            self.screen_before_ui = self.display_screen;
+            self.display_screen = DisplayScreen::Ui;
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -111,30 +330,13 @@ impl<'d> BspEsp32S3CoreBoard<'d> {
         let sysloop = EspSystemEventLoop::take()?;
         let nvs = EspNvsPartition::<NvsDefault>::take()?;
 
+        boot::enter("storage");
         let mut fs_init = false;
         if let Ok(_) = BspEsp32S3CoreBoard::init_fs() {
             fs_init = true;
         }
-        // 初始化spi, 这初始化能够让spi外设被多个设备使用
-        let driver_config = Default::default();
-        let spi_drv = SpiDriver::new(
-            peripherals.spi2,
-            peripherals.pins.gpio12,
-            peripherals.pins.gpio11,
-            None::<Gpio0>,
-            &driver_config,
-        )?;
-
-        let wifi = EspWifi::new(peripherals.modem, sysloop, Some(nvs.clone()))?;
-        log::info!("start init ws2812");
-        #[cfg(feature = "use_ws2812")]
-        let ws2812 = Ws2812Esp32Rmt::new(peripherals.rmt.channel0, peripherals.pins.gpio48)
-            .map_err(|e| anyhow!("Ws2812Esp32Rmt error: {:?}", e))?;
-
-        let mut temp_sensor =
-            TempSensorDriver::new(&TempSensorConfig::default(), peripherals.temp_sensor)?;
-        temp_sensor.enable()?;
 
+        boot::enter("io expander");
         let i2c_driver = I2cDriver::new(
             peripherals.i2c0,
             peripherals.pins.gpio41,
@@ -142,54 +344,112 @@ impl<'d> BspEsp32S3CoreBoard<'d> {
             &I2cConfig::new().baudrate(FromValueType::kHz(100).into()),
         )?;
         let mut xl9555 = XL9555::init(i2c_driver, (false, false, false));
-        xl9555.xl9555_ioconfig(0b1111_0000_0000_0000)?;
+        xl9555.xl9555_ioconfig(BUTTON_PINS_MASK)?;
         let xl9555_ref = Rc::new(RefCell::new(xl9555));
 
-        let mut board = Self {
-            #[cfg(feature = "use_ws2812")]
-            ws2812,
-            wifi,
-            mcu_temperature: temp_sensor,
-            wifi_ssid: WIFI_SSID.to_string(),
-            wifi_password: WIFI_PASSWD.to_string(),
-            fs_init,
-            #[cfg(feature = "use_st7789")]
-            display: None,
-            xl9555: xl9555_ref,
-            #[cfg(feature = "use_st7789")]
-            display_backlight_pin: xl9555::Pin::P13,
-            #[cfg(feature = "use_st7789")]
-            display_rst_pin: xl9555::Pin::P12,
-        };
-
+        // 屏幕先于其他外设初始化, 后面的阶段显示在启动画面上
+        boot::enter("display");
+        // 初始化spi, 这初始化能够让spi外设被多个设备使用
+        #[cfg(not(feature = "use_psram_framebuffer"))]
+        let driver_config = Default::default();
+        #[cfg(feature = "use_psram_framebuffer")]
+        let driver_config = SpiDriverConfig::new().dma(Dma::Auto(DISPLAY_DMA_BUFFER_SIZE));
+        let spi_drv = SpiDriver::new(
+            peripherals.spi2,
+            peripherals.pins.gpio12,
+            peripherals.pins.gpio11,
+            None::<Gpio0>,
+            &driver_config,
+        )?;
         let spi_config =
             esp_idf_svc::hal::spi::SpiConfig::new().baudrate(FromValueType::MHz(30).into());
         let spi_bus_drv = SpiBusDriver::new(spi_drv, &spi_config)?;
-        #[cfg(feature = "use_st7789")]
-        {
-            let _backlight_pin = xl9555::io::Output::new(
-                &board.xl9555,
-                board.display_backlight_pin,
-                xl9555::PinState::High,
-            );
-            let _rst_pin = xl9555::io::Output::new(
-                &board.xl9555,
-                board.display_rst_pin,
-                xl9555::PinState::High,
-            );
-            let display = display::new(
+
+        // 屏幕参数优先使用配置文件中的设置
+        #[cfg(feature = "display")]
+        let mut display_panel = DISPLAY_PANEL;
+        #[cfg(feature = "display")]
+        if fs_init {
+            let mut loaded = display_panel;
+            match loaded
+                .load(display::DISPLAY_CONFIG_PATH)
+                .and_then(|()| loaded.check::<DisplayModel>())
+            {
+                Ok(()) => display_panel = loaded,
+                Err(err) => log::info!("use default display config: {:?}", err),
+            }
+        }
+        #[cfg(feature = "display")]
+        let mut display = {
+            let _backlight_pin =
+                xl9555::io::Output::new(&xl9555_ref, DISPLAY_BACKLIGHT_PIN, xl9555::PinState::High);
+            let _rst_pin =
+                xl9555::io::Output::new(&xl9555_ref, DISPLAY_RST_PIN, xl9555::PinState::High);
+            Some(display::new(
                 spi_bus_drv,
                 PinDriver::output(peripherals.pins.gpio21)?,
                 PinDriver::output(peripherals.pins.gpio13)?,
-                ST7789,
+                DISPLAY_MODEL,
                 display_buf,
-                240,
-                320,
-            )?;
-            board.display = Some(display);
+                &display_panel,
+            )?)
+        };
+        // 上次启动 panic 时先显示保存的错误信息
+        if let Some((stage, message)) = boot::take_fatal_record() {
+            log::error!("last boot panicked at stage {stage}: {message}");
+            #[cfg(feature = "display")]
+            if let Some(display) = display.as_mut() {
+                show_fatal_error(display, &stage, &message, false);
+                thread::sleep(boot::FATAL_SCREEN_TIME);
+            }
         }
-        log::info!("board init success");
-        Ok(board)
+
+        let result = (|| -> Result<Self> {
+            boot::enter("wifi");
+            #[cfg(feature = "display")]
+            show_boot_stage(&mut display);
+            let wifi = EspWifi::new(peripherals.modem, sysloop.clone(), Some(nvs.clone()))?;
+
+            boot::enter("peripherals");
+            #[cfg(feature = "display")]
+            show_boot_stage(&mut display);
+            log::info!("start init ws2812");
+            #[cfg(feature = "use_ws2812")]
+            let ws2812 = Ws2812Esp32Rmt::new(peripherals.rmt.channel0, peripherals.pins.gpio48)
+                .map_err(|e| anyhow!("Ws2812Esp32Rmt error: {:?}", e))?;
+            let mut temp_sensor =
+                TempSensorDriver::new(&TempSensorConfig::default(), peripherals.temp_sensor)?;
+            temp_sensor.enable()?;
+
+            Ok(Self {
+                #[cfg(feature = "use_ws2812")]
+                ws2812,
+                wifi,
+                sysloop,
+                mcu_temperature: temp_sensor,
+                wifi_ssid: WIFI_SSID.to_string(),
+                wifi_password: WIFI_PASSWD.to_string(),
+                fs_init,
+                #[cfg(feature = "display")]
+                display: display.take(),
+                #[cfg(feature = "display")]
+                display_panel,
+                xl9555: Rc::clone(&xl9555_ref),
+                #[cfg(feature = "display")]
+                display_backlight_pin: DISPLAY_BACKLIGHT_PIN,
+                #[cfg(feature = "display")]
+                display_rst_pin: DISPLAY_RST_PIN,
+            })
+        })();
+        // 出错时屏幕还没有交给 board, 在这里显示错误信息
+        #[cfg(feature = "display")]
+        if let (Err(err), Some(display)) = (&result, display.as_mut()) {
+            show_fatal_error(display, boot::stage(), &format!("{err:?}"), true);
+        }
+        if result.is_ok() {
+            log::info!("board init success");
+        }
+        result
     }
 
     fn init_fs() -> Result<()> {
@@ -257,6 +517,42 @@ impl<'d> BspEsp32S3CoreBoard<'d> {
         Ok(())
     }
 
+    /// 订阅 wifi 事件, 断开后在后台线程中按指数退避重连, 连上后退避时间复位.
+    /// 返回的订阅被丢弃后停止重连
+    pub fn wifi_reconnect_start(&self) -> Result<EspSubscription<'static, System>> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let subscription = self.sysloop.subscribe::<WifiEvent, _>(move |event| {
+            let connected = match event {
+                WifiEvent::StaConnected(_) => true,
+                WifiEvent::StaDisconnected(_) => false,
+                _ => return,
+            };
+            let _ = sender.send(connected);
+        })?;
+        thread::Builder::new().stack_size(4 * 1024).spawn(move || {
+            let mut backoff = WIFI_RECONNECT_MIN;
+            while let Ok(connected) = receiver.recv() {
+                if connected {
+                    backoff = WIFI_RECONNECT_MIN;
+                    continue;
+                }
+                thread::sleep(backoff);
+                // 等待期间已经重新连上时不再重连, 多次断开事件只重连一次
+                if receiver.try_iter().last() == Some(true) {
+                    backoff = WIFI_RECONNECT_MIN;
+                    continue;
+                }
+                log::info!("wifi reconnect after {:?}", backoff);
+                if let Err(err) = esp!(unsafe { sys::esp_wifi_connect() }) {
+                    log::warn!("wifi reconnect failed: {:?}", err);
+                }
+                backoff = (backoff * 2).min(WIFI_RECONNECT_MAX);
+            }
+            log::info!("wifi reconnect stopped");
+        })?;
+        Ok(subscription)
+    }
+
     /// 连接wifi 传入 wifi 名称和密码
     pub fn wifi_connect(&mut self) -> Result<(), anyhow::Error> {
         if self.wifi.is_connected()? {
@@ -329,11 +625,33 @@ impl<'d> BspEsp32S3CoreBoard<'d> {
         board: Arc<Mutex<BoardEsp32State>>,
     ) -> Result<JoinHandle<Result<()>>, anyhow::Error> {
         let ble = BLEDevice::take();
+        // 每次上电随机生成6位配对码, 使用 LE Secure Connections 配对并绑定,
+        // 绑定信息由 NimBLE 保存在 nvs 中(CONFIG_BT_NIMBLE_NVS_PERSIST)
+        let passkey = unsafe { sys::esp_random() } % 1_000_000;
+        ble.security()
+            .set_auth(AuthReq::Bond | AuthReq::Mitm | AuthReq::Sc)
+            .set_passkey(passkey)
+            .set_io_cap(SecurityIOCap::DisplayOnly)
+            .resolve_rpa();
+        // 尽量协商大的 mtu, 减少 NUS 等服务的分包
+        ble.set_preferred_mtu(BLE_PREFERRED_MTU)?;
         let ble_advertising = ble.get_advertising();
         let server = ble.get_server();
-        server.on_connect(|server, desc| {
+        let nus_sessions = ble_uart::nus_service_start(server, Arc::clone(&board))?;
+        ble_file::file_service_start(server)?;
+        ble_ota::ota_service_start(server)?;
+        #[cfg(feature = "enable_ble_hid")]
+        let _hid_handle = crate::ble_hid::hid_service_start(server, Arc::clone(&board))?;
+        let board_connect = Arc::clone(&board);
+        server.on_connect(move |server, desc| {
             log::info!("Client connected: {:?}", desc);
 
+            {
+                let mut board_state = board_connect.lock().expect("Failed to lock board mutex");
+                board_state.ble_connections = server.connected_count();
+                board_state.display_power = Some(PowerRequest::Wake);
+            }
+
             // 优化通信, 低功耗使用
             server
                 .update_conn_params(desc.conn_handle(), 24, 48, 0, 60)
@@ -347,8 +665,36 @@ impl<'d> BspEsp32S3CoreBoard<'d> {
             }
         });
 
-        server.on_disconnect(|_desc, reason| {
+        // 只有开始 passkey 配对时 NimBLE 才会请求配对码, 这时交给主循环显示到屏幕上.
+        // 已绑定的设备和不配对的连接不显示
+        let board_passkey = Arc::clone(&board);
+        server.on_passkey_request(move || {
+            log::info!("Passkey requested");
+            let mut board_state = board_passkey.lock().expect("Failed to lock board mutex");
+            board_state.ble_pairing_passkey = Some(passkey);
+            board_state.display_power = Some(PowerRequest::Wake);
+            passkey
+        });
+
+        let board_auth = Arc::clone(&board);
+        server.on_authentication_complete(move |desc, result| {
+            log::info!("Authentication complete: {:?}, {:?}", desc, result);
+            board_auth
+                .lock()
+                .expect("Failed to lock board mutex")
+                .ble_pairing_passkey = None;
+        });
+
+        let board_disconnect = Arc::clone(&board);
+        server.on_disconnect(move |desc, reason| {
             log::info!("Disconnected from server: {:?}", reason);
+            nus_sessions
+                .lock()
+                .expect("Failed to lock nus sessions")
+                .remove(&desc.conn_handle());
+            let mut board_state = board_disconnect.lock().expect("Failed to lock board mutex");
+            board_state.ble_pairing_passkey = None;
+            board_state.ble_connections = board_state.ble_connections.saturating_sub(1);
         });
         let service = server.create_service(uuid128!("fafafafa-fafa-fafa-fafa-fafafafafafa"));
         let static_characteristic = service.lock().create_characteristic(
@@ -359,7 +705,7 @@ impl<'d> BspEsp32S3CoreBoard<'d> {
             .lock()
             .set_value(b"Hello World, this is static, TOTHTOT");
 
-        // 通知特征, 能够向订阅这个uuid的设备不停发送消息
+        // 通知特征, 能够向订阅这个uuid的设备不停发送二进制遥测数据, 格式见 telemetry 模块
         let notifying_characteristic = service.lock().create_characteristic(
             uuid128!("a3c87500-8ed3-4bdf-8a39-a01bebede295"),
             NimbleProperties::READ | NimbleProperties::NOTIFY,
@@ -368,10 +714,13 @@ impl<'d> BspEsp32S3CoreBoard<'d> {
             .lock()
             .set_value(b"Hello World, this is notify, TOTHTOT");
 
-        // 写入特征, 通过这个uuid能够向esp发送数据
+        // 写入特征, 通过这个uuid能够向esp发送数据, 写入前必须完成加密配对
         let write_characteristic = service.lock().create_characteristic(
             uuid128!("3c9a3f00-8ed3-4bdf-8a39-a01bebede295"),
-            NimbleProperties::READ | NimbleProperties::WRITE,
+            NimbleProperties::READ
+                | NimbleProperties::WRITE
+                | NimbleProperties::WRITE_ENC
+                | NimbleProperties::WRITE_AUTHEN,
         );
         write_characteristic
             .lock()
@@ -387,11 +736,16 @@ impl<'d> BspEsp32S3CoreBoard<'d> {
             });
 
         // 设置蓝牙名称, 以及透传uuid, 开始蓝牙服务
-        ble_advertising.lock().set_data(
-            BLEAdvertisementData::new()
-                .name("ESP32-GATT-Server")
-                .add_service_uuid(uuid128!("fafafafa-fafa-fafa-fafa-fafafafafafa")),
-        )?;
+        let mut advertisement = BLEAdvertisementData::new();
+        advertisement
+            .name("ESP32-GATT-Server")
+            .add_service_uuid(uuid128!("fafafafa-fafa-fafa-fafa-fafafafafafa"));
+        // 作为键盘广播, 让手机和电脑能识别成输入设备
+        #[cfg(feature = "enable_ble_hid")]
+        advertisement
+            .appearance(BLE_APPEARANCE_KEYBOARD)
+            .add_service_uuid(esp32_nimble::utilities::BleUuid::from_uuid16(0x1812));
+        ble_advertising.lock().set_data(&mut advertisement)?;
         ble_advertising.lock().start()?;
 
         // 开启连接日志显示
@@ -400,20 +754,27 @@ impl<'d> BspEsp32S3CoreBoard<'d> {
         let handle = thread::spawn(move || -> Result<()> {
             let mut counter = 0;
             loop {
-                thread::sleep(Duration::from_millis(1000));
+                let interval = board
+                    .lock()
+                    .expect("Failed to lock board mutex")
+                    .telemetry_interval;
+                thread::sleep(interval);
                 let board_state = board.lock().expect("Failed to lock board mutex");
-                let temp = board_state.current_mcu_temperature;
                 if board_state.exit == true {
                     log::info!("ble server stopped");
                     break Ok(());
                 }
-                let notify_str = String::from(format!("running:{counter},temp:{temp}",));
-                // log::info!("{notify_str}");
+                let record = telemetry::TelemetryRecord {
+                    flags: board_state.telemetry_flags(),
+                    counter,
+                    temperature: board_state.current_mcu_temperature,
+                    uptime: console::uptime_secs() as u32,
+                };
                 notifying_characteristic
                     .lock()
-                    .set_value(notify_str.as_bytes())
+                    .set_value(&record.encode())
                     .notify();
-                counter += 1;
+                counter = counter.wrapping_add(1);
             }
         });
         Ok(handle)
@@ -427,11 +788,53 @@ impl<'d> BspEsp32S3CoreBoard<'d> {
         &self.wifi_password
     }
 
+    /// 读取按键状态, 第 i 位为 1 表示第 i 个按键按下
+    pub fn buttons_pressed(&self) -> Result<u8> {
+        let pins = self.xl9555.borrow_mut().read_all_value()?;
+        Ok(((!pins & BUTTON_PINS_MASK) >> BUTTON_PINS_MASK.trailing_zeros()) as u8)
+    }
+
     pub fn get_mcu_temperature(&mut self) -> Result<f32> {
         let temp = self.mcu_temperature.get_celsius()?;
         Ok(temp)
     }
 
+    /// fat 文件系统已用空间和总空间, 单位 byte
+    pub fn fs_usage(&self) -> Result<(u64, u64)> {
+        if !self.fs_init {
+            return Err(anyhow::Error::msg("fs is not mounted"));
+        }
+        let mount_point = CString::new(storage::FS_MOUNT_POINT)?;
+        let (mut total, mut free) = (0_u64, 0_u64);
+        esp!(unsafe { sys::esp_vfs_fat_info(mount_point.as_ptr(), &mut total, &mut free) })?;
+        Ok((total - free, total))
+    }
+
+    /// 当前连接的 wifi 信号强度
+    pub fn wifi_rssi(&self) -> Option<i8> {
+        let mut ap_info = sys::wifi_ap_record_t::default();
+        esp!(unsafe { sys::esp_wifi_sta_get_ap_info(&mut ap_info) }).ok()?;
+        Some(ap_info.rssi)
+    }
+
+    /// 当前获取到的 ip 地址, 未连接时返回 None
+    pub fn wifi_ip(&self) -> Option<Ipv4Addr> {
+        let ip = self.wifi.sta_netif().get_ip_info().ok()?.ip;
+        (!ip.is_unspecified()).then_some(ip)
+    }
+
+    /// 收集状态面板需要显示的数据
+    pub fn dashboard_data(&self, state: &BoardEsp32State) -> DashboardData {
+        DashboardData {
+            ip: self.wifi_ip(),
+            rssi: self.wifi_rssi(),
+            ble_connections: state.ble_connections,
+            temperature: state.current_mcu_temperature,
+            uptime: console::uptime_secs(),
+            fat_usage: self.fs_usage().ok(),
+        }
+    }
+
     pub fn get_fs_init(&self) -> bool {
         self.fs_init
     }
@@ -441,7 +844,7 @@ impl<'d> BspEsp32S3CoreBoard<'d> {
     }
 
     /// 屏幕复位
-    #[cfg(feature = "use_st7789")]
+    #[cfg(feature = "display")]
     pub fn display_rst(&self) -> Result<()> {
         if self.display.is_none() {
             return Err(anyhow::Error::msg("display is none"));
@@ -455,8 +858,76 @@ impl<'d> BspEsp32S3CoreBoard<'d> {
             .set_value(self.display_rst_pin, true)?;
         Ok(())
     }
+    /// 当前的屏幕参数
+    #[cfg(feature = "display")]
+    pub fn display_panel(&self) -> PanelConfig {
+        self.display_panel
+    }
+
+    /// 运行时修改屏幕参数(旋转, 镜像, 偏移, 颜色等), 并保存到配置文件
+    #[cfg(feature = "display")]
+    pub fn display_configure(&mut self, panel: PanelConfig) -> Result<()> {
+        // 检查不通过时保留原来的屏幕
+        panel.check::<DisplayModel>()?;
+        let display = self
+            .display
+            .take()
+            .ok_or(anyhow::Error::msg("display is none"))?;
+        self.display = Some(display::reconfigure(display, &panel)?);
+        self.display_panel = panel;
+        log::info!("display reconfigured: {:?}", panel);
+        // 屏幕已经按新参数工作, 保存失败只影响下次启动
+        if self.fs_init {
+            if let Err(err) = panel.save(display::DISPLAY_CONFIG_PATH) {
+                log::warn!("save display config failed: {:?}", err);
+            }
+        }
+        Ok(())
+    }
+
+    /// 屏幕重新初始化失败后为 false, 之后不再绘制
+    #[cfg(feature = "display")]
+    pub fn has_display(&self) -> bool {
+        self.display.is_some()
+    }
+
+    /// 取出屏幕用于绘制
+    #[cfg(feature = "display")]
+    pub fn display_mut(&mut self) -> Result<&mut MyDisplay<'d>> {
+        self.display
+            .as_mut()
+            .ok_or(anyhow::Error::msg("display is none"))
+    }
+    /// 进入下一个启动阶段, 有屏幕时更新启动画面
+    pub fn boot_stage(&mut self, stage: &'static str) {
+        boot::enter(stage);
+        #[cfg(feature = "display")]
+        show_boot_stage(&mut self.display);
+    }
+
+    /// 出错重启前在屏幕上显示错误信息
+    #[cfg(feature = "display")]
+    pub fn display_fatal_error(&mut self, err: &anyhow::Error) {
+        if let Some(display) = self.display.as_mut() {
+            show_fatal_error(display, boot::stage(), &format!("{err:?}"), true);
+        }
+    }
+
+    /// 关闭屏幕时先关背光再让屏幕睡眠, 打开时顺序相反, 避免看到睡眠过程中的画面
+    #[cfg(feature = "display")]
+    pub fn display_set_sleep(&mut self, sleep: bool) -> Result<()> {
+        if sleep {
+            self.display_set_backlight(0)?;
+            display::set_sleep(self.display_mut()?, true)?;
+        } else {
+            display::set_sleep(self.display_mut()?, false)?;
+            self.display_set_backlight(255)?;
+        }
+        log::info!("display {}", if sleep { "sleep" } else { "wake" });
+        Ok(())
+    }
     /// 设置屏幕背光, 目前的显示屏的背光引脚有xl9555控制基本不支持pwm, 所以暂时用true和false控制
-    #[cfg(feature = "use_st7789")]
+    #[cfg(feature = "display")]
     pub fn display_set_backlight(&self, backlight: u8) -> Result<()> {
         if self.display.is_none() {
             return Err(anyhow::Error::msg("display is none"));
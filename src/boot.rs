@@ -0,0 +1,107 @@
+//! 启动进度和致命错误处理. 启动时记录当前所在的初始化阶段, 屏幕初始化后显示启动画面,
+//! 出错时在屏幕上显示出错的阶段和错误信息, 等待一段时间后重启.
+//! 固件使用 panic_abort, panic 时主线程可能正在使用屏幕, 不能在 panic hook 中绘制,
+//! 所以 panic 信息保存在重启后不清除的 rtc 内存中, 下次启动时显示.
+
+use esp_idf_svc::hal::reset;
+use std::ptr::addr_of_mut;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// 启动画面列出的初始化阶段, 按执行顺序排列
+pub const BOOT_STAGES: [&str; 7] = [
+    "storage",
+    "io expander",
+    "display",
+    "wifi",
+    "peripherals",
+    "ble",
+    "http server",
+];
+/// 启动完成后的阶段, 主循环出错时显示
+pub const STAGE_RUNNING: &str = "running";
+/// 重启前错误画面显示的时间
+pub const FATAL_SCREEN_TIME: Duration = Duration::from_secs(5);
+
+static STAGE: Mutex<&str> = Mutex::new(BOOT_STAGES[0]);
+
+/// 进入下一个初始化阶段
+pub fn enter(stage: &'static str) {
+    log::info!("boot stage: {stage}");
+    *STAGE.lock().expect("Could not lock boot stage") = stage;
+}
+
+pub fn stage() -> &'static str {
+    // panic hook 中使用, 不能等待锁
+    STAGE.try_lock().map(|stage| *stage).unwrap_or("unknown")
+}
+
+/// 阶段在启动列表中的序号, 启动完成后等于列表长度
+pub fn stage_index(stage: &str) -> usize {
+    BOOT_STAGES
+        .iter()
+        .position(|name| *name == stage)
+        .unwrap_or(BOOT_STAGES.len())
+}
+
+/// 保存的 panic 信息有效时的标记, 上电后 rtc 内存是随机值
+const FATAL_MAGIC: u32 = 0x5041_4e43;
+
+#[repr(C)]
+struct FatalRecord {
+    magic: u32,
+    stage: [u8; 24],
+    message: [u8; 200],
+}
+
+#[link_section = ".rtc_noinit"]
+static mut FATAL_RECORD: FatalRecord = FatalRecord {
+    magic: 0,
+    stage: [0; 24],
+    message: [0; 200],
+};
+
+/// 复制字符串到定长数组, 超出时在字符边界截断, 剩余部分填 0
+fn copy_text(dst: &mut [u8], text: &str) {
+    let mut len = text.len().min(dst.len());
+    while !text.is_char_boundary(len) {
+        len -= 1;
+    }
+    dst.fill(0);
+    dst[..len].copy_from_slice(&text.as_bytes()[..len]);
+}
+
+fn read_text(src: &[u8]) -> String {
+    let len = src.iter().position(|b| *b == 0).unwrap_or(src.len());
+    String::from_utf8_lossy(&src[..len]).into_owned()
+}
+
+/// panic 时把阶段和信息保存到 rtc 内存, 然后交给默认的处理函数打印并重启
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let record = unsafe { &mut *addr_of_mut!(FATAL_RECORD) };
+        copy_text(&mut record.stage, stage());
+        copy_text(&mut record.message, &info.to_string());
+        record.magic = FATAL_MAGIC;
+        default_hook(info);
+    }));
+}
+
+/// 取出上次 panic 时保存的阶段和信息, 取出后清除
+pub fn take_fatal_record() -> Option<(String, String)> {
+    let record = unsafe { &mut *addr_of_mut!(FATAL_RECORD) };
+    if record.magic != FATAL_MAGIC {
+        return None;
+    }
+    record.magic = 0;
+    Some((read_text(&record.stage), read_text(&record.message)))
+}
+
+/// 出错后等待错误画面显示一段时间再重启
+pub fn restart_after_error(err: &anyhow::Error) -> ! {
+    log::error!("fatal error at stage {}: {:?}", stage(), err);
+    thread::sleep(FATAL_SCREEN_TIME);
+    reset::restart()
+}
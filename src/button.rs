@@ -0,0 +1,145 @@
+//! xl9555 输入引脚上的 4 个按键. 主循环每次读取引脚电平交给 `Buttons::poll`,
+//! 消抖后产生按下, 松开, 长按和双击事件, 通过 channel 发送给所有订阅者.
+//! 不依赖 esp-idf, 状态机的测试在模拟器中运行.
+
+use crate::widget::InputEvent;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+pub const BUTTON_COUNT: usize = 4;
+/// 电平变化后保持这么久才认为按键状态改变
+const DEBOUNCE: Duration = Duration::from_millis(30);
+/// 按住超过这个时间产生长按事件
+pub const LONG_PRESS: Duration = Duration::from_millis(800);
+/// 松开后在这个时间内再次按下产生双击事件
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonAction {
+    Press,
+    /// 松开, 带上按住的时间
+    Release {
+        held: Duration,
+    },
+    /// 按住超过 `LONG_PRESS`, 每次按下只产生一次
+    LongPress,
+    /// 在第二次按下的 Press 事件之后产生
+    DoubleClick,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ButtonEvent {
+    /// 按键序号, 0 对应 P14
+    pub button: usize,
+    pub action: ButtonAction,
+}
+
+impl ButtonEvent {
+    /// 按键对应的界面输入: 短按 4 个按键为上, 下, 确认, 返回, 长按前两个按键为上一页, 下一页
+    pub fn input_event(&self) -> Option<InputEvent> {
+        const CLICKS: [InputEvent; BUTTON_COUNT] = [
+            InputEvent::Up,
+            InputEvent::Down,
+            InputEvent::Select,
+            InputEvent::Back,
+        ];
+        match self.action {
+            ButtonAction::LongPress if self.button == 0 => Some(InputEvent::Left),
+            ButtonAction::LongPress if self.button == 1 => Some(InputEvent::Right),
+            ButtonAction::Release { held } if held < LONG_PRESS => CLICKS.get(self.button).copied(),
+            _ => None,
+        }
+    }
+
+    /// 长按返回键进入或退出多页面界面
+    pub fn toggles_ui(&self) -> bool {
+        self.action == ButtonAction::LongPress && self.button == 3
+    }
+
+    /// 双击返回键立即关闭屏幕
+    pub fn sleeps_display(&self) -> bool {
+        self.action == ButtonAction::DoubleClick && self.button == 3
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+struct ButtonState {
+    /// 消抖后的状态
+    pressed: bool,
+    /// 电平和消抖后的状态不一致的开始时间
+    changing_since: Option<Instant>,
+    pressed_at: Option<Instant>,
+    long_pressed: bool,
+    /// 上一次短按松开的时间, 用于判断双击
+    released_at: Option<Instant>,
+}
+
+/// 按键消抖和事件分发
+#[derive(Default)]
+pub struct Buttons {
+    states: [ButtonState; BUTTON_COUNT],
+    subscribers: Vec<Sender<ButtonEvent>>,
+}
+
+impl Buttons {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 订阅之后产生的按键事件, Receiver 被丢弃后自动取消订阅
+    pub fn subscribe(&mut self) -> Receiver<ButtonEvent> {
+        let (sender, receiver) = channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    /// 处理一次采样, pressed 的第 i 位为 1 表示第 i 个按键按下
+    pub fn poll(&mut self, pressed: u8, now: Instant) {
+        for button in 0..BUTTON_COUNT {
+            for action in self.update(button, pressed & (1 << button) != 0, now) {
+                self.emit(ButtonEvent { button, action });
+            }
+        }
+    }
+
+    fn update(&mut self, button: usize, down: bool, now: Instant) -> Vec<ButtonAction> {
+        let state = &mut self.states[button];
+        let mut actions = Vec::new();
+        if down == state.pressed {
+            state.changing_since = None;
+        } else if now - *state.changing_since.get_or_insert(now) >= DEBOUNCE {
+            state.pressed = down;
+            state.changing_since = None;
+            if down {
+                actions.push(ButtonAction::Press);
+                if state
+                    .released_at
+                    .take()
+                    .is_some_and(|at| now - at <= DOUBLE_CLICK)
+                {
+                    actions.push(ButtonAction::DoubleClick);
+                }
+                state.pressed_at = Some(now);
+                state.long_pressed = false;
+            } else {
+                let held = state.pressed_at.map_or(Duration::ZERO, |at| now - at);
+                actions.push(ButtonAction::Release { held });
+                // 长按后松开不作为双击的第一次
+                state.released_at = (!state.long_pressed).then_some(now);
+            }
+        }
+        if state.pressed
+            && !state.long_pressed
+            && state.pressed_at.is_some_and(|at| now - at >= LONG_PRESS)
+        {
+            state.long_pressed = true;
+            actions.push(ButtonAction::LongPress);
+        }
+        actions
+    }
+
+    fn emit(&mut self, event: ButtonEvent) {
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event).is_ok());
+    }
+}
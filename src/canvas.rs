@@ -0,0 +1,157 @@
+//! 远程绘制画面(电子看板), http 等外部接口发来的绘制命令都放进
+//! `BoardEsp32State::display_commands` 队列, 由主循环的屏幕管理统一执行,
+//! 避免多个线程同时操作屏幕. 画布保存上次清屏后的所有命令, 需要重绘时重新执行一遍.
+
+use crate::font::BitmapFont;
+use crate::image_loader;
+use anyhow::{anyhow, Result};
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::Point,
+    mono_font::{
+        ascii::{FONT_10X20, FONT_6X10},
+        MonoTextStyle,
+    },
+    pixelcolor::{Rgb565, Rgb888},
+    text::{Baseline, Text},
+    Drawable,
+};
+use std::path::PathBuf;
+
+/// 画布最多保存的命令数, 超过后丢弃最早的绘制命令
+const CANVAS_COMMANDS_MAX: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DisplayCommand {
+    /// 用指定颜色清屏
+    Clear(Rgb565),
+    /// 在左上角为 position 的位置绘制文字, size 为 1 或 2
+    Text {
+        text: String,
+        position: Point,
+        color: Rgb565,
+        size: u8,
+    },
+    /// 绘制 fat 中的图片, position 为 None 时居中
+    Image {
+        path: PathBuf,
+        position: Option<Point>,
+    },
+}
+
+/// 解析 `RRGGBB` 形式的颜色
+pub fn parse_color(value: &str) -> Result<Rgb565> {
+    let value = value.trim_start_matches('#');
+    if value.len() != 6 {
+        return Err(anyhow!("color must be RRGGBB"));
+    }
+    let rgb = u32::from_str_radix(value, 16)?;
+    Ok(Rgb888::new((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8).into())
+}
+
+#[derive(Default)]
+pub struct Canvas {
+    commands: Vec<DisplayCommand>,
+}
+
+impl Canvas {
+    /// 执行一条命令并记录下来
+    pub fn execute<D>(
+        &mut self,
+        display: &mut D,
+        command: DisplayCommand,
+        font: Option<&mut BitmapFont>,
+    ) -> Result<()>
+    where
+        D: DrawTarget<Color = Rgb565>,
+        D::Error: core::fmt::Debug,
+    {
+        let result = Self::draw_command(display, &command, font);
+        if let DisplayCommand::Image { path, .. } = &command {
+            // 同一个文件被新上传的图片覆盖了, 重绘时旧位置会显示新图片
+            self.commands.retain(|old| match old {
+                DisplayCommand::Image { path: old_path, .. } => old_path != path,
+                _ => true,
+            });
+        }
+        if matches!(command, DisplayCommand::Clear(_)) {
+            self.commands.clear();
+        } else if self.commands.len() >= CANVAS_COMMANDS_MAX {
+            // 保留第一条清屏命令, 背景色不会丢
+            let first = usize::from(matches!(self.commands[0], DisplayCommand::Clear(_)));
+            self.commands.remove(first);
+        }
+        self.commands.push(command);
+        result
+    }
+
+    /// 重新执行所有记录的命令
+    pub fn redraw<D>(&mut self, display: &mut D, mut font: Option<&mut BitmapFont>) -> Result<()>
+    where
+        D: DrawTarget<Color = Rgb565>,
+        D::Error: core::fmt::Debug,
+    {
+        if !matches!(self.commands.first(), Some(DisplayCommand::Clear(_))) {
+            display
+                .clear(Rgb565::new(0, 0, 0))
+                .map_err(|e| anyhow!("clear display failed: {:?}", e))?;
+        }
+        for command in &self.commands {
+            if let Err(err) = Self::draw_command(display, command, font.as_deref_mut()) {
+                log::warn!("canvas redraw {:?} failed: {:?}", command, err);
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_command<D>(
+        display: &mut D,
+        command: &DisplayCommand,
+        font: Option<&mut BitmapFont>,
+    ) -> Result<()>
+    where
+        D: DrawTarget<Color = Rgb565>,
+        D::Error: core::fmt::Debug,
+    {
+        match command {
+            DisplayCommand::Clear(color) => display
+                .clear(*color)
+                .map_err(|e| anyhow!("clear display failed: {:?}", e)),
+            DisplayCommand::Text {
+                text,
+                position,
+                color,
+                size,
+            } => match font {
+                // ascii 以外的字符使用位图字体
+                Some(font) if !text.is_ascii() => {
+                    font.draw_text(display, text, *position, *color, None)?;
+                    Ok(())
+                }
+                _ => {
+                    let mono_font = if *size >= 2 { &FONT_10X20 } else { &FONT_6X10 };
+                    let style = MonoTextStyle::new(mono_font, *color);
+                    Text::with_baseline(text, *position, style, Baseline::Top)
+                        .draw(display)
+                        .map_err(|e| anyhow!("draw text failed: {:?}", e))?;
+                    Ok(())
+                }
+            },
+            DisplayCommand::Image { path, position } => {
+                let top_left = match position {
+                    Some(position) => *position,
+                    None => {
+                        let info = image_loader::image_info(path)?;
+                        let size = display.bounding_box().size;
+                        Point::new(
+                            (size.width as i32 - info.width as i32) / 2,
+                            (size.height as i32 - info.height as i32) / 2,
+                        )
+                    }
+                };
+                image_loader::draw_image(display, path, top_left)?;
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! 温度曲线, 纵轴按数据范围自动缩放, 标出最高和最低温度
+
+use crate::history::{HistoryRange, HISTORY_POINTS};
+use anyhow::anyhow;
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::Point,
+    mono_font::{
+        ascii::{FONT_10X20, FONT_6X10},
+        MonoTextStyle,
+    },
+    pixelcolor::Rgb565,
+    prelude::{RgbColor, WebColors},
+    primitives::{Circle, Line, Polyline, Primitive, PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+    Drawable,
+};
+
+/// 曲线左边留给纵轴刻度的宽度
+const AXIS_LABEL_WIDTH: i32 = 30;
+/// 纵轴最小跨度, 温度变化很小时曲线不会被放大成噪声
+const MIN_SPAN: f32 = 2.0;
+
+/// 纵轴范围取整到 1 度
+fn axis_range(points: &[f32]) -> (f32, f32) {
+    let min = points.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = points.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let (mut low, mut high) = (min.floor(), max.ceil());
+    if high - low < MIN_SPAN {
+        low = ((min + max) / 2.0 - MIN_SPAN / 2.0).floor();
+        high = low + MIN_SPAN;
+    }
+    (low, high)
+}
+
+/// 整屏绘制温度曲线, points 按时间顺序排列, 最后一个点在最右边
+pub fn draw_temperature_chart<D>(
+    display: &mut D,
+    points: &[f32],
+    range: HistoryRange,
+) -> anyhow::Result<()>
+where
+    D: DrawTarget<Color = Rgb565>,
+    D::Error: core::fmt::Debug,
+{
+    let bounds = display.bounding_box();
+    let title_font = if bounds.size.height >= 240 {
+        &FONT_10X20
+    } else {
+        &FONT_6X10
+    };
+    let label_style = MonoTextStyle::new(&FONT_6X10, Rgb565::BLACK);
+    display
+        .clear(Rgb565::WHITE)
+        .map_err(|e| anyhow!("clear display failed: {:?}", e))?;
+    Text::with_baseline(
+        &format!("Temp {}", range.name()),
+        bounds.top_left + Point::new(4, 2),
+        MonoTextStyle::new(title_font, Rgb565::BLACK),
+        Baseline::Top,
+    )
+    .draw(display)
+    .map_err(|e| anyhow!("draw chart title failed: {:?}", e))?;
+
+    let top = bounds.top_left.y + title_font.character_size.height as i32 + 8;
+    let plot = Rectangle::with_corners(
+        Point::new(bounds.top_left.x + AXIS_LABEL_WIDTH, top),
+        bounds.top_left + bounds.size - Point::new(6, 16),
+    );
+    if plot.is_zero_sized() {
+        return Ok(());
+    }
+    let Some(bottom_right) = plot.bottom_right() else {
+        return Ok(());
+    };
+    let axis_style = PrimitiveStyle::with_stroke(Rgb565::BLACK, 1);
+    let grid_style = PrimitiveStyle::with_stroke(Rgb565::CSS_LIGHT_GRAY, 1);
+    let error = |e| anyhow!("draw chart failed: {:?}", e);
+
+    // 横轴时间刻度
+    let bottom_style = TextStyleBuilder::new().baseline(Baseline::Top);
+    Text::with_text_style(
+        &format!("-{}", range.name()),
+        Point::new(plot.top_left.x, bottom_right.y + 3),
+        label_style,
+        bottom_style.alignment(Alignment::Left).build(),
+    )
+    .draw(display)
+    .map_err(error)?;
+    Text::with_text_style(
+        "now",
+        Point::new(bottom_right.x, bottom_right.y + 3),
+        label_style,
+        bottom_style.alignment(Alignment::Right).build(),
+    )
+    .draw(display)
+    .map_err(error)?;
+
+    if points.is_empty() {
+        Text::with_alignment("no data", plot.center(), label_style, Alignment::Center)
+            .draw(display)
+            .map_err(error)?;
+    } else {
+        let (low, high) = axis_range(points);
+        let height = (plot.size.height - 1) as f32;
+        let y_of = |value: f32| bottom_right.y - ((value - low) / (high - low) * height) as i32;
+        // 纵轴刻度和网格: 最低, 中间, 最高
+        for value in [low, (low + high) / 2.0, high] {
+            let y = y_of(value);
+            Line::new(
+                Point::new(plot.top_left.x, y),
+                Point::new(bottom_right.x, y),
+            )
+            .into_styled(grid_style)
+            .draw(display)
+            .map_err(error)?;
+            Text::with_text_style(
+                &format!("{value:.1}"),
+                Point::new(plot.top_left.x - 3, y),
+                label_style,
+                TextStyleBuilder::new()
+                    .alignment(Alignment::Right)
+                    .baseline(Baseline::Middle)
+                    .build(),
+            )
+            .draw(display)
+            .map_err(error)?;
+        }
+
+        // 点数不满时曲线靠右, 左边表示还没有数据的时间
+        let step = (plot.size.width - 1) as f32 / (HISTORY_POINTS - 1) as f32;
+        let first = HISTORY_POINTS - points.len().min(HISTORY_POINTS);
+        let line: Vec<Point> = points
+            .iter()
+            .rev()
+            .take(HISTORY_POINTS)
+            .rev()
+            .enumerate()
+            .map(|(i, value)| {
+                let x = plot.top_left.x + ((first + i) as f32 * step) as i32;
+                Point::new(x, y_of(*value))
+            })
+            .collect();
+        Polyline::new(&line)
+            .into_styled(PrimitiveStyle::with_stroke(Rgb565::CSS_STEEL_BLUE, 2))
+            .draw(display)
+            .map_err(error)?;
+
+        // 最高温度标在点的上方, 最低温度标在下方
+        let visible = &points[points.len() - line.len()..];
+        let extreme = |pick_max: bool| {
+            visible
+                .iter()
+                .enumerate()
+                .reduce(|a, b| {
+                    if (b.1 > a.1) == pick_max && b.1 != a.1 {
+                        b
+                    } else {
+                        a
+                    }
+                })
+                .map(|(i, value)| (line[i], *value))
+        };
+        for (pick_max, color, offset, baseline) in [
+            (true, Rgb565::RED, -4, Baseline::Bottom),
+            (false, Rgb565::BLUE, 4, Baseline::Top),
+        ] {
+            let Some((point, value)) = extreme(pick_max) else {
+                continue;
+            };
+            Circle::with_center(point, 5)
+                .into_styled(PrimitiveStyle::with_fill(color))
+                .draw(display)
+                .map_err(error)?;
+            // 标签不超出曲线区域
+            let alignment = if point.x > plot.center().x {
+                Alignment::Right
+            } else {
+                Alignment::Left
+            };
+            let y = (point.y + offset).clamp(plot.top_left.y + 10, bottom_right.y - 10);
+            Text::with_text_style(
+                &format!("{value:.1}"),
+                Point::new(point.x, y),
+                MonoTextStyle::new(&FONT_6X10, color),
+                TextStyleBuilder::new()
+                    .alignment(alignment)
+                    .baseline(baseline)
+                    .build(),
+            )
+            .draw(display)
+            .map_err(error)?;
+        }
+    }
+
+    // 坐标轴最后画, 不会被曲线盖住
+    Line::new(plot.top_left, Point::new(plot.top_left.x, bottom_right.y))
+        .into_styled(axis_style)
+        .draw(display)
+        .map_err(error)?;
+    Line::new(Point::new(plot.top_left.x, bottom_right.y), bottom_right)
+        .into_styled(axis_style)
+        .draw(display)
+        .map_err(error)?;
+    Ok(())
+}
@@ -0,0 +1,572 @@
+//! 时钟和日历画面, 数字表盘和指针表盘两种样式, 下方显示日期, 时区, 闹钟和当月日历.
+//! 时间来自 sntp 同步后的系统时间, 时区用相对 utc 的固定分钟数表示, 不处理夏令时.
+//! 闹钟只在表盘上标出时间, 不会响铃.
+//! 画面由定时器触发刷新, 每次只重绘表盘区域, 日期或设置变化时才整屏重绘.
+
+use anyhow::anyhow;
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Point, Size},
+    mono_font::{
+        ascii::{FONT_10X20, FONT_6X10},
+        MonoFont, MonoTextStyle, MonoTextStyleBuilder,
+    },
+    pixelcolor::Rgb565,
+    prelude::{RgbColor, WebColors},
+    primitives::{Circle, Line, Primitive, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+    Drawable,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 刷新时钟的定时器周期, 不和整秒对齐, 秒数变化后最多晚半个周期显示
+pub const CLOCK_TICK: Duration = Duration::from_millis(500);
+/// 最多保存的闹钟数
+pub const ALARMS_MAX: usize = 4;
+/// 系统时间早于 2024-01-01 时认为还没有同步
+const TIME_VALID_AFTER: u64 = 1_704_067_200;
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// 由定时器置位, 主循环显示时钟画面时清除.
+/// 定时器回调运行在 esp_timer 任务中, 不能等待主循环持有的状态锁, 所以不放在 board 状态里
+static TICK: AtomicBool = AtomicBool::new(false);
+
+/// 定时器回调, 通知主循环刷新时钟
+pub fn tick() {
+    TICK.store(true, Ordering::Relaxed);
+}
+
+/// 取出并清除刷新通知
+pub fn take_tick() -> bool {
+    TICK.swap(false, Ordering::Relaxed)
+}
+
+/// 表盘样式
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ClockFace {
+    #[default]
+    Digital,
+    Analog,
+}
+
+impl ClockFace {
+    pub const NAMES: &'static str = "digital analog";
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "digital" => Some(Self::Digital),
+            "analog" => Some(Self::Analog),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Alarm {
+    pub hour: u8,
+    pub minute: u8,
+}
+
+impl Alarm {
+    /// 解析 `HH:MM`
+    pub fn parse(text: &str) -> Option<Self> {
+        let (hour, minute) = text.split_once(':')?;
+        let (hour, minute) = (hour.parse().ok()?, minute.parse().ok()?);
+        (hour < 24 && minute < 60).then_some(Self { hour, minute })
+    }
+}
+
+impl core::fmt::Display for Alarm {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+/// 时钟画面的设置
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClockSettings {
+    pub face: ClockFace,
+    /// 时区, 相对 utc 的分钟数
+    pub utc_offset: i32,
+    /// 按时间排序的闹钟
+    pub alarms: Vec<Alarm>,
+}
+
+/// 解析时区, 支持 `+8`, `-5:30`, `+08:00`
+pub fn parse_utc_offset(text: &str) -> Option<i32> {
+    let (sign, rest) = match text.as_bytes().first()? {
+        b'+' => (1, &text[1..]),
+        b'-' => (-1, &text[1..]),
+        _ => (1, text),
+    };
+    let (hours, minutes) = rest.split_once(':').unwrap_or((rest, "0"));
+    let (hours, minutes): (i32, i32) = (hours.parse().ok()?, minutes.parse().ok()?);
+    (hours <= 14 && (0..60).contains(&minutes)).then_some(sign * (hours * 60 + minutes))
+}
+
+pub fn format_utc_offset(offset: i32) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.unsigned_abs();
+    format!("UTC{sign}{:02}:{:02}", offset / 60, offset % 60)
+}
+
+/// 按时区换算后的日期和时间
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalTime {
+    pub year: i32,
+    pub month: u8,
+    pub day: u8,
+    /// 0 表示星期一
+    pub weekday: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl LocalTime {
+    pub fn from_unix(secs: i64, utc_offset: i32) -> Self {
+        let secs = secs + utc_offset as i64 * 60;
+        let days = secs.div_euclid(86400);
+        let time = secs.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+        Self {
+            year,
+            month,
+            day,
+            // 1970-01-01 是星期四
+            weekday: (days + 3).rem_euclid(7) as u8,
+            hour: (time / 3600) as u8,
+            minute: (time / 60 % 60) as u8,
+            second: (time % 60) as u8,
+        }
+    }
+
+    /// 当前时间, 还没有同步时返回 None
+    pub fn now(utc_offset: i32) -> Option<Self> {
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+        (secs >= TIME_VALID_AFTER).then(|| Self::from_unix(secs as i64, utc_offset))
+    }
+
+    pub fn date(&self) -> (i32, u8, u8) {
+        (self.year, self.month, self.day)
+    }
+}
+
+/// 1970-01-01 以来的天数换算成年月日, 算法来自 Howard Hinnant 的 civil_from_days
+fn civil_from_days(days: i64) -> (i32, u8, u8) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = (yoe + era * 400 + i64::from(month <= 2)) as i32;
+    (year, month, day)
+}
+
+fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// 表盘区域和下方信息的起始行
+struct Layout {
+    dial: Rectangle,
+    /// 数字表盘一个数字的宽度
+    digit: i32,
+    info_top: i32,
+}
+
+impl Layout {
+    fn new(bounds: Rectangle, face: ClockFace) -> Self {
+        let (width, height) = (bounds.size.width as i32, bounds.size.height as i32);
+        let (dial, digit) = match face {
+            ClockFace::Digital => {
+                let digit = (width / 6).min(height / 8).max(8);
+                let size = Size::new(
+                    seven_segment_width(digit) as u32,
+                    (digit * 2 + SECOND_BAR_GAP + SECOND_BAR_HEIGHT) as u32,
+                );
+                let top_left = Point::new(bounds.center().x - size.width as i32 / 2, 8);
+                (Rectangle::new(top_left, size), digit)
+            }
+            ClockFace::Analog => {
+                let diameter = width.min(height * 3 / 5) - 8;
+                let top_left = Point::new(bounds.center().x - diameter / 2, 4);
+                (
+                    Rectangle::new(top_left, Size::new_equal(diameter as u32)),
+                    0,
+                )
+            }
+        };
+        let info_top = dial.top_left.y + dial.size.height as i32 + 8;
+        Self {
+            dial,
+            digit,
+            info_top,
+        }
+    }
+}
+
+/// 整屏绘制时钟画面
+pub fn draw_clock_screen<D>(
+    display: &mut D,
+    settings: &ClockSettings,
+    time: Option<LocalTime>,
+) -> anyhow::Result<()>
+where
+    D: DrawTarget<Color = Rgb565>,
+    D::Error: core::fmt::Debug,
+{
+    let bounds = display.bounding_box();
+    let error = |e| anyhow!("draw clock failed: {:?}", e);
+    display.clear(Rgb565::WHITE).map_err(error)?;
+    let layout = Layout::new(bounds, settings.face);
+    let font = if bounds.size.height >= 240 {
+        &FONT_10X20
+    } else {
+        &FONT_6X10
+    };
+    let left = bounds.top_left.x + 4;
+    let mut y = layout.info_top;
+    let date = match time {
+        Some(time) => format!(
+            "{}-{:02}-{:02} {}",
+            time.year, time.month, time.day, WEEKDAYS[time.weekday as usize]
+        ),
+        None => "time not synced".to_string(),
+    };
+    draw_text(display, &date, Point::new(left, y), font, Rgb565::BLACK)?;
+    y += font.character_size.height as i32 + 4;
+
+    // 时区和闹钟一行放不下时按词换行, 不截断
+    let mut words = vec![("", format_utc_offset(settings.utc_offset))];
+    for (i, alarm) in settings.alarms.iter().enumerate() {
+        words.push(match i {
+            0 => ("  ", format!("alarm {alarm}")),
+            _ => (" ", alarm.to_string()),
+        });
+    }
+    let columns = (bounds.size.width as i32 - (left - bounds.top_left.x)).max(0) as usize
+        / FONT_6X10.character_size.width as usize;
+    for line in wrap_words(&words, columns) {
+        draw_text(
+            display,
+            &line,
+            Point::new(left, y),
+            &FONT_6X10,
+            Rgb565::CSS_DIM_GRAY,
+        )?;
+        y += FONT_6X10.character_size.height as i32 + 2;
+    }
+    y += 4;
+
+    if let Some(time) = time {
+        draw_calendar(display, time, Point::new(left, y))?;
+    }
+    draw_clock_time(display, settings, time)
+}
+
+/// 只重绘表盘区域, 每秒调用
+pub fn draw_clock_time<D>(
+    display: &mut D,
+    settings: &ClockSettings,
+    time: Option<LocalTime>,
+) -> anyhow::Result<()>
+where
+    D: DrawTarget<Color = Rgb565>,
+    D::Error: core::fmt::Debug,
+{
+    let layout = Layout::new(display.bounding_box(), settings.face);
+    match settings.face {
+        ClockFace::Digital => draw_digital(display, layout.dial, layout.digit, time),
+        ClockFace::Analog => draw_analog(display, layout.dial, &settings.alarms, time),
+    }
+}
+
+/// 按 columns 列贪心换行, 每个词带上它和前一个词之间的分隔符, 行首的分隔符省略
+fn wrap_words(words: &[(&str, String)], columns: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for (separator, word) in words {
+        match lines.last_mut() {
+            Some(line)
+                if line.chars().count() + separator.len() + word.chars().count() <= columns =>
+            {
+                line.push_str(separator);
+                line.push_str(word);
+            }
+            _ => lines.push(word.clone()),
+        }
+    }
+    lines
+}
+
+fn draw_text<D>(
+    display: &mut D,
+    text: &str,
+    position: Point,
+    font: &MonoFont,
+    color: Rgb565,
+) -> anyhow::Result<()>
+where
+    D: DrawTarget<Color = Rgb565>,
+    D::Error: core::fmt::Debug,
+{
+    // 超出屏幕宽度时截断
+    let width = display.bounding_box().size.width as i32 - position.x;
+    let columns = (width.max(0) as u32 / font.character_size.width) as usize;
+    let text: String = text.chars().take(columns).collect();
+    Text::with_baseline(
+        &text,
+        position,
+        MonoTextStyle::new(font, color),
+        Baseline::Top,
+    )
+    .draw(display)
+    .map_err(|e| anyhow!("draw clock text failed: {:?}", e))?;
+    Ok(())
+}
+
+/// 数字表盘下方秒数进度条的间距和高度
+const SECOND_BAR_GAP: i32 = 6;
+const SECOND_BAR_HEIGHT: i32 = 3;
+/// 七段数码管的段, 依次为 a 到 g
+const SEGMENT_DIGITS: [u8; 10] = [0x3f, 0x06, 0x5b, 0x4f, 0x66, 0x6d, 0x7d, 0x07, 0x7f, 0x6f];
+/// 时间未同步时显示的横杠
+const SEGMENT_DASH: u8 = 0x40;
+
+/// `HH:MM` 的宽度, digit 为一个数字的宽度, 高度是宽度的两倍
+fn seven_segment_width(digit: i32) -> i32 {
+    4 * digit + 3 * (digit / 4) + digit / 2
+}
+
+fn draw_digital<D>(
+    display: &mut D,
+    area: Rectangle,
+    digit: i32,
+    time: Option<LocalTime>,
+) -> anyhow::Result<()>
+where
+    D: DrawTarget<Color = Rgb565>,
+    D::Error: core::fmt::Debug,
+{
+    let error = |e| anyhow!("draw digital clock failed: {:?}", e);
+    display.fill_solid(&area, Rgb565::WHITE).map_err(error)?;
+    let gap = digit / 4;
+    let thickness = (digit / 6).max(2);
+    let segments: [u8; 4] = match time {
+        Some(time) => [
+            SEGMENT_DIGITS[(time.hour / 10) as usize],
+            SEGMENT_DIGITS[(time.hour % 10) as usize],
+            SEGMENT_DIGITS[(time.minute / 10) as usize],
+            SEGMENT_DIGITS[(time.minute % 10) as usize],
+        ],
+        None => [SEGMENT_DASH; 4],
+    };
+    let mut x = area.top_left.x;
+    let y = area.top_left.y;
+    for (i, segments) in segments.iter().enumerate() {
+        draw_seven_segment(display, Point::new(x, y), digit, thickness, *segments)?;
+        x += digit + gap;
+        if i == 1 {
+            // 冒号
+            for dot_y in [y + digit / 2, y + digit * 3 / 2 - thickness] {
+                Rectangle::new(Point::new(x, dot_y), Size::new_equal(thickness as u32))
+                    .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
+                    .draw(display)
+                    .map_err(error)?;
+            }
+            x += digit / 2;
+        }
+    }
+
+    // 秒数用进度条表示
+    let bar_top = y + digit * 2 + SECOND_BAR_GAP;
+    let bar_width = area.size.width * time.map_or(0, |time| time.second as u32) / 59;
+    Rectangle::new(
+        Point::new(area.top_left.x, bar_top),
+        Size::new(bar_width, SECOND_BAR_HEIGHT as u32),
+    )
+    .into_styled(PrimitiveStyle::with_fill(Rgb565::CSS_STEEL_BLUE))
+    .draw(display)
+    .map_err(error)?;
+    Ok(())
+}
+
+fn draw_seven_segment<D>(
+    display: &mut D,
+    top_left: Point,
+    width: i32,
+    thickness: i32,
+    segments: u8,
+) -> anyhow::Result<()>
+where
+    D: DrawTarget<Color = Rgb565>,
+    D::Error: core::fmt::Debug,
+{
+    let (x, y, t) = (top_left.x, top_left.y, thickness);
+    let half = width;
+    let horizontal = Size::new((width - 2 * t).max(1) as u32, t as u32);
+    let vertical = Size::new(t as u32, (half - t).max(1) as u32);
+    let rects = [
+        Rectangle::new(Point::new(x + t, y), horizontal),
+        Rectangle::new(Point::new(x + width - t, y + t), vertical),
+        Rectangle::new(Point::new(x + width - t, y + half), vertical),
+        Rectangle::new(Point::new(x + t, y + 2 * half - t), horizontal),
+        Rectangle::new(Point::new(x, y + half), vertical),
+        Rectangle::new(Point::new(x, y + t), vertical),
+        Rectangle::new(Point::new(x + t, y + half - t / 2), horizontal),
+    ];
+    for (i, rect) in rects.iter().enumerate() {
+        if segments & (1 << i) != 0 {
+            rect.into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
+                .draw(display)
+                .map_err(|e| anyhow!("draw digital clock failed: {:?}", e))?;
+        }
+    }
+    Ok(())
+}
+
+/// 从 12 点方向顺时针转过 fraction 圈, 距离圆心 length 的点
+fn dial_point(center: Point, fraction: f32, length: f32) -> Point {
+    let angle = fraction * core::f32::consts::TAU;
+    center
+        + Point::new(
+            (length * angle.sin()) as i32,
+            -(length * angle.cos()) as i32,
+        )
+}
+
+fn draw_analog<D>(
+    display: &mut D,
+    area: Rectangle,
+    alarms: &[Alarm],
+    time: Option<LocalTime>,
+) -> anyhow::Result<()>
+where
+    D: DrawTarget<Color = Rgb565>,
+    D::Error: core::fmt::Debug,
+{
+    let error = |e| anyhow!("draw analog clock failed: {:?}", e);
+    let center = area.center();
+    let radius = area.size.width as f32 / 2.0;
+    Circle::new(area.top_left, area.size.width)
+        .into_styled(
+            PrimitiveStyleBuilder::new()
+                .fill_color(Rgb565::WHITE)
+                .stroke_color(Rgb565::BLACK)
+                .stroke_width(2)
+                .build(),
+        )
+        .draw(display)
+        .map_err(error)?;
+    for hour in 0..12 {
+        let (inner, width) = if hour % 3 == 0 { (0.8, 3) } else { (0.88, 1) };
+        let fraction = hour as f32 / 12.0;
+        Line::new(
+            dial_point(center, fraction, radius * inner),
+            dial_point(center, fraction, radius - 3.0),
+        )
+        .into_styled(PrimitiveStyle::with_stroke(Rgb565::BLACK, width))
+        .draw(display)
+        .map_err(error)?;
+    }
+    // 闹钟标记在时针指向的位置
+    for alarm in alarms {
+        let fraction = (alarm.hour % 12) as f32 / 12.0 + alarm.minute as f32 / 720.0;
+        Circle::with_center(dial_point(center, fraction, radius * 0.68), 7)
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::CSS_ORANGE))
+            .draw(display)
+            .map_err(error)?;
+    }
+    let Some(time) = time else {
+        return Ok(());
+    };
+
+    let minutes = time.minute as f32 + time.second as f32 / 60.0;
+    let hands = [
+        (
+            (time.hour % 12) as f32 / 12.0 + minutes / 720.0,
+            0.5,
+            (radius / 16.0).max(3.0) as u32,
+            Rgb565::BLACK,
+        ),
+        (
+            minutes / 60.0,
+            0.78,
+            (radius / 24.0).max(2.0) as u32,
+            Rgb565::BLACK,
+        ),
+        (time.second as f32 / 60.0, 0.86, 1, Rgb565::RED),
+    ];
+    for (fraction, length, width, color) in hands {
+        Line::new(center, dial_point(center, fraction, radius * length))
+            .into_styled(PrimitiveStyle::with_stroke(color, width))
+            .draw(display)
+            .map_err(error)?;
+    }
+    Circle::with_center(center, 5)
+        .into_styled(PrimitiveStyle::with_fill(Rgb565::RED))
+        .draw(display)
+        .map_err(error)?;
+    Ok(())
+}
+
+/// 当月日历, 星期一在第一列, 今天反色显示. 剩余高度不够时不绘制
+fn draw_calendar<D>(display: &mut D, today: LocalTime, top_left: Point) -> anyhow::Result<()>
+where
+    D: DrawTarget<Color = Rgb565>,
+    D::Error: core::fmt::Debug,
+{
+    let char_size = FONT_6X10.character_size;
+    let (cell_width, row_height) = (char_size.width as i32 * 3, char_size.height as i32 + 1);
+    let bottom = display.bounding_box().bottom_right().map_or(0, |p| p.y);
+    if top_left.y + row_height * 7 > bottom + 1 {
+        return Ok(());
+    }
+    let error = |e| anyhow!("draw calendar failed: {:?}", e);
+    let header: Vec<&str> = WEEKDAYS.iter().map(|day| &day[..2]).collect();
+    draw_text(
+        display,
+        &header.join(" "),
+        top_left,
+        &FONT_6X10,
+        Rgb565::CSS_DIM_GRAY,
+    )?;
+
+    let first_weekday = (today.weekday as i32 - (today.day as i32 - 1)).rem_euclid(7);
+    let normal = MonoTextStyle::new(&FONT_6X10, Rgb565::BLACK);
+    let highlight = MonoTextStyleBuilder::new()
+        .font(&FONT_6X10)
+        .text_color(Rgb565::WHITE)
+        .background_color(Rgb565::CSS_STEEL_BLUE)
+        .build();
+    for day in 1..=days_in_month(today.year, today.month) {
+        let cell = first_weekday + day as i32 - 1;
+        let position = top_left
+            + Point::new(
+                (cell % 7) * cell_width + char_size.width as i32 * 2,
+                (cell / 7 + 1) * row_height,
+            );
+        let style = if day == today.day { highlight } else { normal };
+        Text::with_text_style(
+            &format!("{day:>2}"),
+            position,
+            style,
+            TextStyleBuilder::new()
+                .alignment(Alignment::Right)
+                .baseline(Baseline::Top)
+                .build(),
+        )
+        .draw(display)
+        .map_err(error)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,280 @@
+use crate::board::BoardEsp32State;
+use crate::clock::{self, Alarm, ClockFace};
+use crate::display::{DisplayScreen, PanelConfig};
+use crate::history::HistoryRange;
+use crate::qr::QrContent;
+use crate::screen_power::PowerRequest;
+use crate::widget::InputEvent;
+use crate::{image_loader, storage};
+use esp_idf_svc::sys;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// 单行命令的最大长度, 超过后丢弃这一行
+pub const CONSOLE_LINE_MAX: usize = 128;
+/// 允许设置的遥测通知间隔
+const TELEMETRY_INTERVAL_RANGE_MS: RangeInclusive<u64> = 100..=60_000;
+
+/// 按行组包, 收到 '\n' 后返回完整的一行
+#[derive(Default)]
+pub struct LineBuffer {
+    buf: Vec<u8>,
+    overflow: bool,
+}
+
+impl LineBuffer {
+    /// 追加收到的数据, 返回其中所有完整的行
+    pub fn push(&mut self, data: &[u8]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for &byte in data {
+            match byte {
+                b'\r' => {}
+                b'\n' => {
+                    if !self.overflow {
+                        lines.push(String::from_utf8_lossy(&self.buf).into_owned());
+                    }
+                    self.buf.clear();
+                    self.overflow = false;
+                }
+                _ if self.buf.len() >= CONSOLE_LINE_MAX => self.overflow = true,
+                _ => self.buf.push(byte),
+            }
+        }
+        lines
+    }
+}
+
+/// 执行一行控制台命令, 返回需要回复给对端的文本
+pub fn execute(line: &str, board: &Arc<Mutex<BoardEsp32State>>) -> String {
+    let mut args = line.split_whitespace();
+    match args.next() {
+        None => String::new(),
+        Some("help") => {
+            "commands: help, temp, uptime, heap, interval [ms], display [key value], screen [name], image <file>, chart [range], qr [web|wifi|text], clock [face|tz|alarm], nav <event>, sleep [now|off|secs], wake, reboot\n"
+                .to_string()
+        }
+        Some("temp") => {
+            let board_state = board.lock().expect("Failed to lock board mutex");
+            let mut reply = format!("mcu temperature: {}\n", board_state.current_mcu_temperature);
+            for sensor in &board_state.external_sensors {
+                reply += &format!(
+                    "{} temperature: {} ({}s ago)\n",
+                    sensor.name,
+                    sensor.temperature,
+                    uptime_secs().saturating_sub(sensor.updated_at)
+                );
+            }
+            reply
+        }
+        Some("uptime") => format!("uptime: {}s\n", uptime_secs()),
+        Some("heap") => format!("free heap: {}\n", unsafe { sys::esp_get_free_heap_size() }),
+        Some("interval") => {
+            let mut board_state = board.lock().expect("Failed to lock board mutex");
+            match args.next().map(str::parse::<u64>) {
+                None => {}
+                Some(Ok(ms)) if TELEMETRY_INTERVAL_RANGE_MS.contains(&ms) => {
+                    board_state.telemetry_interval = Duration::from_millis(ms);
+                }
+                Some(_) => {
+                    return format!(
+                        "interval must be {}..={} ms\n",
+                        TELEMETRY_INTERVAL_RANGE_MS.start(),
+                        TELEMETRY_INTERVAL_RANGE_MS.end()
+                    )
+                }
+            }
+            format!(
+                "telemetry interval: {} ms\n",
+                board_state.telemetry_interval.as_millis()
+            )
+        }
+        #[cfg(feature = "enable_ble_hid")]
+        Some("key") => {
+            let Some(key) = args.next().and_then(crate::ble_hid::HidKey::parse) else {
+                return "usage: key <a-z|0-9|f1-f24|enter|space|volup|voldown|mute|play|next|prev>\n"
+                    .to_string();
+            };
+            let mut board_state = board.lock().expect("Failed to lock board mutex");
+            crate::ble_hid::push_key(&mut board_state, key);
+            format!("key: {:?}\n", key)
+        }
+        Some("display") => {
+            let mut board_state = board.lock().expect("Failed to lock board mutex");
+            let Some(mut panel) = board_state.display_config else {
+                return "display disabled\n".to_string();
+            };
+            match (args.next(), args.next()) {
+                (None, _) => panel.to_conf(),
+                (Some(key), Some(value)) => match panel
+                    .set_option(key, value)
+                    .and_then(|()| crate::board::check_display_panel(&panel))
+                {
+                    Ok(()) => {
+                        board_state.display_config = Some(panel);
+                        format!("display {key} = {value}\n")
+                    }
+                    Err(err) => format!("{err}\n"),
+                },
+                (Some(_), None) => {
+                    format!(
+                        "usage: display <key> <value>, keys: {}\n",
+                        PanelConfig::OPTIONS
+                    )
+                }
+            }
+        }
+        Some("screen") => {
+            let mut board_state = board.lock().expect("Failed to lock board mutex");
+            match args.next() {
+                None => format!("screen: {:?}\n", board_state.display_screen),
+                Some(name) => match DisplayScreen::parse(name) {
+                    Some(screen) => {
+                        board_state.display_screen = screen;
+                        format!("screen: {:?}\n", screen)
+                    }
+                    None => format!("screens: {}\n", DisplayScreen::NAMES),
+                },
+            }
+        }
+        Some("image") => {
+            let Some(name) = args.next() else {
+                return "usage: image <file in /fat>\n".to_string();
+            };
+            let path = match storage::fat_path(name) {
+                Ok(path) => path,
+                Err(err) => return format!("{err}\n"),
+            };
+            if let Err(err) = image_loader::image_info(&path) {
+                return format!("{err}\n");
+            }
+            let mut board_state = board.lock().expect("Failed to lock board mutex");
+            board_state.display_image = Some(path);
+            board_state.display_screen = DisplayScreen::Image;
+            // 已经在图片画面时也要重新绘制
+            board_state.display_refresh = true;
+            format!("image: {name}\n")
+        }
+        Some("chart") => {
+            let mut board_state = board.lock().expect("Failed to lock board mutex");
+            if let Some(name) = args.next() {
+                match HistoryRange::parse(name) {
+                    Some(range) => board_state.chart_range = range,
+                    None => return format!("ranges: {}\n", HistoryRange::NAMES),
+                }
+            }
+            board_state.display_screen = DisplayScreen::Chart;
+            format!("chart: {}\n", board_state.chart_range.name())
+        }
+        Some("qr") => {
+            let content = match args.next() {
+                None | Some("web") => QrContent::WebUi,
+                Some("wifi") => QrContent::Wifi,
+                Some(first) => {
+                    let text = std::iter::once(first).chain(args).collect::<Vec<_>>();
+                    QrContent::Text(text.join(" "))
+                }
+            };
+            let mut board_state = board.lock().expect("Failed to lock board mutex");
+            board_state.display_screen = DisplayScreen::Qr;
+            let reply = format!("qr: {:?}\n", content);
+            board_state.display_qr = content;
+            reply
+        }
+        Some("clock") => {
+            const USAGE: &str =
+                "usage: clock [digital|analog], clock tz <+hh:mm>, clock alarm <hh:mm|clear>\n";
+            let mut board_state = board.lock().expect("Failed to lock board mutex");
+            let settings = &mut board_state.clock;
+            match (args.next(), args.next()) {
+                (None, _) => {}
+                (Some("tz"), Some(tz)) => match clock::parse_utc_offset(tz) {
+                    Some(offset) => settings.utc_offset = offset,
+                    None => return USAGE.to_string(),
+                },
+                (Some("alarm"), Some("clear")) => settings.alarms.clear(),
+                (Some("alarm"), Some(time)) => {
+                    let Some(alarm) = Alarm::parse(time) else {
+                        return USAGE.to_string();
+                    };
+                    if !settings.alarms.contains(&alarm) {
+                        if settings.alarms.len() >= clock::ALARMS_MAX {
+                            return format!("at most {} alarms\n", clock::ALARMS_MAX);
+                        }
+                        settings.alarms.push(alarm);
+                        settings.alarms.sort();
+                    }
+                }
+                (Some(face), None) => match ClockFace::parse(face) {
+                    Some(face) => settings.face = face,
+                    None => return format!("faces: {}\n", ClockFace::NAMES),
+                },
+                _ => return USAGE.to_string(),
+            }
+            let alarms: Vec<String> = settings.alarms.iter().map(Alarm::to_string).collect();
+            let reply = format!(
+                "clock: {:?} {}, alarms: [{}]\n",
+                settings.face,
+                clock::format_utc_offset(settings.utc_offset),
+                alarms.join(" ")
+            );
+            board_state.display_screen = DisplayScreen::Clock;
+            reply
+        }
+        Some("nav") => {
+            let Some(event) = args.next().and_then(InputEvent::parse) else {
+                return format!("usage: nav <event>, events: {}\n", InputEvent::NAMES);
+            };
+            let mut board_state = board.lock().expect("Failed to lock board mutex");
+            // 控制台明确要求操作界面, 当前画面不处理输入时先进入多页面界面
+            if !board_state.display_screen.takes_input() {
+                board_state.toggle_ui();
+            }
+            board_state.push_ui_event(event);
+            format!("nav: {:?}\n", event)
+        }
+        Some("sleep") => {
+            let mut board_state = board.lock().expect("Failed to lock board mutex");
+            match args.next() {
+                None => {}
+                Some("now") => {
+                    board_state.display_power = Some(PowerRequest::Sleep);
+                    return "display sleep\n".to_string();
+                }
+                Some("off") => board_state.display_timeout = None,
+                Some(secs) => match secs.parse::<u64>() {
+                    Ok(secs) if secs > 0 => {
+                        board_state.display_timeout = Some(Duration::from_secs(secs));
+                    }
+                    _ => return "usage: sleep [now|off|secs]\n".to_string(),
+                },
+            }
+            match board_state.display_timeout {
+                Some(timeout) => format!("display timeout: {}s\n", timeout.as_secs()),
+                None => "display timeout: off\n".to_string(),
+            }
+        }
+        Some("wake") => {
+            board
+                .lock()
+                .expect("Failed to lock board mutex")
+                .display_power = Some(PowerRequest::Wake);
+            "display wake\n".to_string()
+        }
+        Some("reboot") => {
+            // 延迟重启, 保证回复能够先发出去
+            thread::spawn(|| {
+                thread::sleep(Duration::from_millis(500));
+                unsafe { sys::esp_restart() };
+            });
+            "rebooting\n".to_string()
+        }
+        Some(cmd) => format!("unknown command: {cmd}\n"),
+    }
+}
+
+/// 上电到现在经过的秒数
+pub fn uptime_secs() -> u64 {
+    (unsafe { sys::esp_timer_get_time() } / 1_000_000) as u64
+}
@@ -0,0 +1,193 @@
+//! 状态面板, 在 ferris 下方显示网络, 蓝牙, 温度, 运行时间和文件系统使用情况.
+//! 每一行单独缓存上次绘制的文字, 只重绘内容有变化的行, 减少 spi 传输
+
+use crate::font::BitmapFont;
+use crate::ui;
+use anyhow::anyhow;
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Point, Size},
+    mono_font::{
+        ascii::{FONT_10X20, FONT_6X10},
+        MonoFont, MonoTextStyle,
+    },
+    pixelcolor::Rgb565,
+    prelude::RgbColor,
+    primitives::{Primitive, PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+    Drawable,
+};
+use std::net::Ipv4Addr;
+
+/// 面板显示的行数
+const DASHBOARD_ROWS: usize = 6;
+/// 紧贴 ferris 下方时的起始行
+const DASHBOARD_TOP_BELOW_FERRIS: i32 = 80;
+/// 在配对码区域下方时和配对码的间距
+const DASHBOARD_PASSKEY_GAP: i32 = 8;
+
+/// 面板的字体, 行高和起始行
+struct Layout {
+    mono_font: &'static MonoFont<'static>,
+    row_height: u32,
+    top: i32,
+}
+
+impl Layout {
+    /// 按屏幕尺寸选择能放下所有行的布局: 宽屏幕使用大字体, 优先放在配对码区域下方,
+    /// 放不下时紧贴 ferris 下方, 和配对码重叠的行在配对时不绘制.
+    /// font_height 为位图字体的高度, 有位图字体时行高由它决定
+    fn new(bounds: Rectangle, font_height: Option<u32>) -> Self {
+        let passkey_area = ui::passkey_area(bounds);
+        let below_passkey =
+            passkey_area.top_left.y + passkey_area.size.height as i32 + DASHBOARD_PASSKEY_GAP;
+        let fonts: &[(&'static MonoFont<'static>, u32)] = if bounds.size.width >= 240 {
+            &[(&FONT_10X20, 24), (&FONT_6X10, 12)]
+        } else {
+            &[(&FONT_6X10, 12)]
+        };
+        let bottom = bounds.top_left.y + bounds.size.height as i32;
+        for &(mono_font, mono_row_height) in fonts {
+            let row_height = font_height.map_or(mono_row_height, |height| height + 4);
+            for top in [below_passkey, DASHBOARD_TOP_BELOW_FERRIS] {
+                if top + (row_height as usize * DASHBOARD_ROWS) as i32 <= bottom {
+                    return Self {
+                        mono_font,
+                        row_height,
+                        top,
+                    };
+                }
+            }
+        }
+        // 屏幕太小时压缩行高
+        let available = (bottom - DASHBOARD_TOP_BELOW_FERRIS).max(0) as u32;
+        Self {
+            mono_font: &FONT_6X10,
+            row_height: available / DASHBOARD_ROWS as u32,
+            top: DASHBOARD_TOP_BELOW_FERRIS,
+        }
+    }
+
+    fn row(&self, bounds: Rectangle, index: usize) -> Rectangle {
+        Rectangle::new(
+            Point::new(
+                bounds.top_left.x,
+                self.top + (index as u32 * self.row_height) as i32,
+            ),
+            Size::new(bounds.size.width, self.row_height),
+        )
+    }
+}
+
+/// 面板需要显示的数据
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DashboardData {
+    pub ip: Option<Ipv4Addr>,
+    pub rssi: Option<i8>,
+    pub ble_connections: usize,
+    pub temperature: f32,
+    /// 上电时间, 单位 s
+    pub uptime: u64,
+    /// fat 已用空间和总空间, 单位 byte
+    pub fat_usage: Option<(u64, u64)>,
+}
+
+impl DashboardData {
+    /// 每一行的中文标签, 英文标签和数值
+    fn rows(&self) -> [(&'static str, &'static str, String); DASHBOARD_ROWS] {
+        let ip = self
+            .ip
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|| "-".to_string());
+        let rssi = self
+            .rssi
+            .map(|rssi| format!("{rssi} dBm"))
+            .unwrap_or_else(|| "-".to_string());
+        let fat = self
+            .fat_usage
+            .map(|(used, total)| format!("{}K/{}K", used / 1024, total / 1024))
+            .unwrap_or_else(|| "-".to_string());
+        let uptime = self.uptime;
+        [
+            ("地址", "IP:  ", ip),
+            ("信号", "RSSI:", rssi),
+            ("蓝牙", "BLE: ", format!("{} conn", self.ble_connections)),
+            ("温度", "Temp:", format!("{:.1} C", self.temperature)),
+            (
+                "运行",
+                "Up:  ",
+                format!(
+                    "{}d {:02}:{:02}:{:02}",
+                    uptime / 86400,
+                    uptime / 3600 % 24,
+                    uptime / 60 % 60,
+                    uptime % 60
+                ),
+            ),
+            ("存储", "FAT: ", fat),
+        ]
+    }
+}
+
+#[derive(Default)]
+pub struct Dashboard {
+    /// 上次绘制的文字, None 表示需要重绘
+    drawn: [Option<String>; DASHBOARD_ROWS],
+}
+
+impl Dashboard {
+    /// 屏幕内容被其他画面覆盖后调用, 下次绘制时重绘所有行
+    pub fn invalidate(&mut self) {
+        self.drawn = Default::default();
+    }
+
+    /// 绘制面板, 只刷新有变化的行. 有位图字体时使用中文标签.
+    /// covered 是被其他内容(配对码)占用的区域, 和它重叠的行不绘制, 区域清除后需要调用 `invalidate`
+    pub fn draw<D>(
+        &mut self,
+        display: &mut D,
+        data: &DashboardData,
+        mut font: Option<&mut BitmapFont>,
+        covered: Option<Rectangle>,
+    ) -> anyhow::Result<()>
+    where
+        D: DrawTarget<Color = Rgb565>,
+        D::Error: core::fmt::Debug,
+    {
+        let bounds = display.bounding_box();
+        let layout = Layout::new(bounds, font.as_ref().map(|font| font.height()));
+        let style = MonoTextStyle::new(layout.mono_font, Rgb565::BLACK);
+        for (index, ((label_cn, label_en, value), drawn)) in
+            data.rows().into_iter().zip(&mut self.drawn).enumerate()
+        {
+            let row = layout.row(bounds, index);
+            if covered.is_some_and(|covered| !covered.intersection(&row).is_zero_sized()) {
+                *drawn = None;
+                continue;
+            }
+            let text = match font {
+                Some(_) => format!("{label_cn}: {value}"),
+                None => format!("{label_en} {value}"),
+            };
+            if drawn.as_ref() == Some(&text) {
+                continue;
+            }
+            row.into_styled(PrimitiveStyle::with_fill(Rgb565::WHITE))
+                .draw(display)
+                .map_err(|e| anyhow!("clear dashboard row failed: {:?}", e))?;
+            let position = row.top_left + Point::new(4, 2);
+            match font.as_deref_mut() {
+                Some(font) => {
+                    font.draw_text(display, &text, position, Rgb565::BLACK, None)?;
+                }
+                None => {
+                    Text::with_baseline(&text, position, style, Baseline::Top)
+                        .draw(display)
+                        .map_err(|e| anyhow!("draw dashboard row failed: {:?}", e))?;
+                }
+            }
+            *drawn = Some(text);
+        }
+        Ok(())
+    }
+}
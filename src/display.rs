@@ -1,11 +1,7 @@
-use embedded_graphics::{
-    draw_target::DrawTarget,
-    geometry::Point,
-    image::{Image, ImageRaw, ImageRawLE},
-    pixelcolor::Rgb565,
-    prelude::RgbColor,
-    Drawable,
-};
+use crate::shadow::ShadowDisplay;
+use crate::ui;
+use anyhow::anyhow;
+use embedded_graphics::pixelcolor::Rgb565;
 use embedded_hal_bus::spi::{ExclusiveDevice, NoDelay};
 use esp_idf_svc::hal::spi::SpiBusDriver;
 use esp_idf_svc::hal::{delay::FreeRtos, spi::SpiDriver};
@@ -13,8 +9,303 @@ use mipidsi::{
     interface::{Interface, InterfacePixelFormat, SpiInterface},
     models::Model,
     NoResetPin,
-    {options::ColorInversion, Builder},
+    {
+        options::{ColorInversion, ColorOrder, Orientation, Rotation},
+        Builder,
+    },
 };
+use std::fs;
+
+/// 屏幕配置文件, 每行一个 `key=value`, 可以通过 ble 文件服务修改
+pub const DISPLAY_CONFIG_PATH: &str = "/fat/display.conf";
+
+/// 屏幕上可以显示的画面
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum DisplayScreen {
+    /// ferris 和状态面板
+    #[default]
+    Dashboard,
+    /// 滚动显示最近的日志
+    Log,
+    /// 居中显示 `BoardEsp32State::display_image` 指定的图片
+    Image,
+    /// 远程绘制的画布, 内容由 http 接口推送
+    Canvas,
+    /// 由控件组成的多页面界面, 长按返回键进入和退出, 通过按键或者控制台 `nav` 命令切换页面
+    Ui,
+    /// 芯片温度曲线, 范围由 `BoardEsp32State::chart_range` 选择
+    Chart,
+    /// 二维码, 内容由 `BoardEsp32State::display_qr` 选择
+    Qr,
+    /// 时钟和日历, 样式和时区由 `BoardEsp32State::clock` 设置
+    Clock,
+}
+
+impl DisplayScreen {
+    pub const NAMES: &'static str = "dashboard log image canvas ui chart qr clock";
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "dashboard" => Some(Self::Dashboard),
+            "log" => Some(Self::Log),
+            "image" => Some(Self::Image),
+            "canvas" => Some(Self::Canvas),
+            "ui" => Some(Self::Ui),
+            "chart" => Some(Self::Chart),
+            "qr" => Some(Self::Qr),
+            "clock" => Some(Self::Clock),
+            _ => None,
+        }
+    }
+
+    /// 这个画面是否处理按键等输入事件, 其他画面丢弃输入
+    pub fn takes_input(self) -> bool {
+        matches!(self, Self::Ui | Self::Chart)
+    }
+}
+
+/// 屏幕面板参数, 不同型号和尺寸的屏幕分辨率, 显存偏移, 颜色顺序不同,
+/// 旋转和镜像取决于屏幕的安装方向
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PanelConfig {
+    pub width: u16,
+    pub height: u16,
+    pub offset_x: u16,
+    pub offset_y: u16,
+    pub color_order: ColorOrder,
+    pub inversion: ColorInversion,
+    pub rotation: Rotation,
+    pub mirrored: bool,
+}
+
+#[allow(dead_code)]
+impl PanelConfig {
+    /// 核心板默认的 2.0 寸 ST7789 屏幕
+    pub const ST7789_240X320: Self = Self {
+        width: 240,
+        height: 320,
+        offset_x: 0,
+        offset_y: 0,
+        color_order: ColorOrder::Rgb,
+        inversion: ColorInversion::Inverted,
+        rotation: Rotation::Deg0,
+        mirrored: false,
+    };
+    /// 1.8 寸 ST7735S 屏幕
+    pub const ST7735_128X160: Self = Self {
+        width: 128,
+        height: 160,
+        offset_x: 0,
+        offset_y: 0,
+        color_order: ColorOrder::Bgr,
+        inversion: ColorInversion::Normal,
+        rotation: Rotation::Deg0,
+        mirrored: false,
+    };
+    /// 0.96 寸 ST7735S 屏幕, 显存比实际像素大, 需要偏移
+    pub const ST7735_80X160: Self = Self {
+        width: 80,
+        height: 160,
+        offset_x: 26,
+        offset_y: 1,
+        color_order: ColorOrder::Bgr,
+        inversion: ColorInversion::Inverted,
+        rotation: Rotation::Deg0,
+        mirrored: false,
+    };
+
+    /// 配置项名称, 用于配置文件和控制台命令
+    pub const OPTIONS: &'static str =
+        "width height offset_x offset_y color_order invert rotation mirror";
+
+    /// 修改一个配置项
+    pub fn set_option(&mut self, key: &str, value: &str) -> anyhow::Result<()> {
+        match key {
+            "width" => self.width = value.parse()?,
+            "height" => self.height = value.parse()?,
+            "offset_x" => self.offset_x = value.parse()?,
+            "offset_y" => self.offset_y = value.parse()?,
+            "color_order" => {
+                self.color_order = match value {
+                    "rgb" => ColorOrder::Rgb,
+                    "bgr" => ColorOrder::Bgr,
+                    _ => return Err(anyhow!("color_order must be rgb or bgr")),
+                }
+            }
+            "invert" => {
+                self.inversion = if value.parse()? {
+                    ColorInversion::Inverted
+                } else {
+                    ColorInversion::Normal
+                }
+            }
+            "rotation" => {
+                self.rotation = match value {
+                    "0" => Rotation::Deg0,
+                    "90" => Rotation::Deg90,
+                    "180" => Rotation::Deg180,
+                    "270" => Rotation::Deg270,
+                    _ => return Err(anyhow!("rotation must be 0, 90, 180 or 270")),
+                }
+            }
+            "mirror" => self.mirrored = value.parse()?,
+            _ => return Err(anyhow!("unknown display option: {key}")),
+        }
+        Ok(())
+    }
+
+    pub fn rotation_degrees(&self) -> u16 {
+        match self.rotation {
+            Rotation::Deg0 => 0,
+            Rotation::Deg90 => 90,
+            Rotation::Deg180 => 180,
+            Rotation::Deg270 => 270,
+        }
+    }
+
+    /// 转换成配置文件内容
+    pub fn to_conf(&self) -> String {
+        let color_order = match self.color_order {
+            ColorOrder::Rgb => "rgb",
+            ColorOrder::Bgr => "bgr",
+        };
+        format!(
+            "width={}\nheight={}\noffset_x={}\noffset_y={}\ncolor_order={}\ninvert={}\nrotation={}\nmirror={}\n",
+            self.width,
+            self.height,
+            self.offset_x,
+            self.offset_y,
+            color_order,
+            self.inversion == ColorInversion::Inverted,
+            self.rotation_degrees(),
+            self.mirrored,
+        )
+    }
+
+    /// 从配置文件读取, 文件里没有的配置项保持原值, 文件中有错误时不修改任何配置项
+    pub fn load(&mut self, path: &str) -> anyhow::Result<()> {
+        let conf = fs::read_to_string(path)?;
+        let mut panel = *self;
+        for line in conf.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(anyhow!("invalid display config line: {line}"))?;
+            panel.set_option(key.trim(), value.trim())?;
+        }
+        *self = panel;
+        Ok(())
+    }
+
+    /// 检查尺寸和偏移是否超出型号的显存, 和 mipidsi 初始化时的检查一致.
+    /// 初始化失败时屏幕接口已经交给了 mipidsi, 无法恢复, 所以要在重新初始化之前检查
+    pub fn check<MODEL: Model>(&self) -> anyhow::Result<()> {
+        let (fb_width, fb_height) = MODEL::FRAMEBUFFER_SIZE;
+        if self.width == 0 || self.height == 0 {
+            return Err(anyhow!("display size must not be 0"));
+        }
+        if u32::from(self.width) + u32::from(self.offset_x) > u32::from(fb_width)
+            || u32::from(self.height) + u32::from(self.offset_y) > u32::from(fb_height)
+        {
+            return Err(anyhow!(
+                "display size {}x{} with offset ({}, {}) exceeds {fb_width}x{fb_height}",
+                self.width,
+                self.height,
+                self.offset_x,
+                self.offset_y
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &str) -> anyhow::Result<()> {
+        fs::write(path, self.to_conf())?;
+        Ok(())
+    }
+
+    fn orientation(&self) -> Orientation {
+        let orientation = Orientation::new().rotate(self.rotation);
+        if self.mirrored {
+            orientation.flip_horizontal()
+        } else {
+            orientation
+        }
+    }
+}
+
+/// 按照面板参数初始化屏幕, 并绘制默认画面
+fn init<DI, MODEL>(
+    model: MODEL,
+    di: DI,
+    panel: &PanelConfig,
+) -> anyhow::Result<ShadowDisplay<mipidsi::Display<DI, MODEL, NoResetPin>>>
+where
+    DI: Interface,
+    DI::Error: core::fmt::Debug,
+    MODEL: Model<ColorFormat = Rgb565>,
+    Rgb565: InterfacePixelFormat<DI::Word>,
+{
+    let mut delay = FreeRtos;
+    let display = Builder::new(model, di)
+        .display_size(panel.width, panel.height)
+        .display_offset(panel.offset_x, panel.offset_y)
+        .color_order(panel.color_order)
+        .invert_colors(panel.inversion)
+        .orientation(panel.orientation())
+        .init(&mut delay)
+        .map_err(|e| anyhow!("display init failed: {:?}", e))?;
+    let mut display = ShadowDisplay::new(display);
+    // 屏幕已经可用, 绘制失败不丢掉屏幕
+    let drawn = ui::draw_home(&mut display).and_then(|_| {
+        display
+            .flush()
+            .map_err(|e| anyhow!("display flush failed: {:?}", e))
+    });
+    if let Err(err) = drawn {
+        log::warn!("draw home failed: {:?}", err);
+    }
+    Ok(display)
+}
+
+/// 运行时修改面板参数, 颜色顺序和偏移等只能在初始化时设置, 所以重新初始化屏幕.
+/// mipidsi 初始化失败时接口已经被释放, 不能再使用旧屏幕, 所以参数要先用
+/// `PanelConfig::check` 检查, 这里只剩下 spi 传输错误
+pub fn reconfigure<DI, MODEL>(
+    display: ShadowDisplay<mipidsi::Display<DI, MODEL, NoResetPin>>,
+    panel: &PanelConfig,
+) -> anyhow::Result<ShadowDisplay<mipidsi::Display<DI, MODEL, NoResetPin>>>
+where
+    DI: Interface,
+    DI::Error: core::fmt::Debug,
+    MODEL: Model<ColorFormat = Rgb565>,
+    Rgb565: InterfacePixelFormat<DI::Word>,
+{
+    let (di, model, _rst) = display.into_inner().release();
+    init(model, di, panel)
+}
+
+/// 屏幕进入或退出睡眠, 睡眠时屏幕内容保留但不刷新
+pub fn set_sleep<DI, MODEL>(
+    display: &mut ShadowDisplay<mipidsi::Display<DI, MODEL, NoResetPin>>,
+    sleep: bool,
+) -> anyhow::Result<()>
+where
+    DI: Interface,
+    DI::Error: core::fmt::Debug,
+    MODEL: Model<ColorFormat = Rgb565>,
+    Rgb565: InterfacePixelFormat<DI::Word>,
+{
+    let mut delay = FreeRtos;
+    let display = display.inner_mut();
+    if sleep {
+        display.sleep(&mut delay)
+    } else {
+        display.wake(&mut delay)
+    }
+    .map_err(|e| anyhow!("display sleep failed: {:?}", e))
+}
 
 pub fn new<'d, DC, CS, MODEL>(
     spi: SpiBusDriver<'d, SpiDriver<'d>>,
@@ -22,13 +313,14 @@ pub fn new<'d, DC, CS, MODEL>(
     dc: DC,
     model: MODEL,
     buffer: &'d mut [u8],
-    width: u16,
-    height: u16,
+    panel: &PanelConfig,
 ) -> anyhow::Result<
-    mipidsi::Display<
-        SpiInterface<'d, ExclusiveDevice<SpiBusDriver<'d, SpiDriver<'d>>, CS, NoDelay>, DC>,
-        MODEL,
-        NoResetPin,
+    ShadowDisplay<
+        mipidsi::Display<
+            SpiInterface<'d, ExclusiveDevice<SpiBusDriver<'d, SpiDriver<'d>>, CS, NoDelay>, DC>,
+            MODEL,
+            NoResetPin,
+        >,
     >,
 >
     where
@@ -41,16 +333,5 @@ pub fn new<'d, DC, CS, MODEL>(
     {
     let spi_device = ExclusiveDevice::new_no_delay(spi, cs).unwrap();
     let di = SpiInterface::new(spi_device, dc, buffer);
-    let mut delay = FreeRtos;
-    let mut display = Builder::new(model, di)
-        .display_size(width, height)
-        .invert_colors(ColorInversion::Inverted)
-        .init(&mut delay)
-        .unwrap();
-    display.clear(Rgb565::WHITE).unwrap();
-
-    let image_raw: ImageRawLE<Rgb565> = ImageRaw::new(include_bytes!("../assets/ferris.raw"), 86);
-    let image = Image::new(&image_raw, Point::new(26, 8));
-    image.draw(&mut display).unwrap();
-    Ok(display)
+    init(model, di, panel)
 }
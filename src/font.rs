@@ -0,0 +1,240 @@
+//! 位图字体, 用于显示中文等 embedded-graphics 自带字体不支持的字符.
+//!
+//! 字体文件由 `tools/mkfont.py` 从 ttf 生成, 可以放在 `/fat` 中, 也可以用
+//! `include_bytes!` 编译进固件. 文件格式(小端):
+//!
+//! | 偏移 | 长度      | 内容                                        |
+//! |------|-----------|---------------------------------------------|
+//! | 0    | 4         | 魔数 `BFNT`                                 |
+//! | 4    | 1         | 版本号, 当前为 1                            |
+//! | 5    | 1         | 行高, 单位像素                              |
+//! | 6    | 2         | 保留                                        |
+//! | 8    | 4         | 字形数量 n                                  |
+//! | 12   | 8 * n     | 索引, 每项为 codepoint u32 + 字形偏移 u32, 按 codepoint 升序 |
+//! | ...  |           | 字形: 宽度 u8, 步进 u8, 点阵(每行 ceil(宽度/8) 字节, 高位在左, 共行高行) |
+//!
+//! 查找字形时在文件里二分查找索引, 不需要把整个字体读进内存, 最近用过的字形缓存在内存中.
+
+use anyhow::{anyhow, Result};
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Point, Size},
+    pixelcolor::Rgb565,
+    primitives::Rectangle,
+    Pixel,
+};
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+const FONT_MAGIC: &[u8; 4] = b"BFNT";
+const FONT_VERSION: u8 = 1;
+const FONT_HEADER_LEN: u32 = 12;
+const FONT_INDEX_ENTRY_LEN: u32 = 8;
+/// 缓存的字形数量
+const GLYPH_CACHE_MAX: usize = 128;
+
+/// 字体数据来源, 可以是文件也可以是编译进固件的数据
+pub trait FontSource: Send {
+    fn read_at(&mut self, offset: u32, buf: &mut [u8]) -> Result<()>;
+}
+
+impl FontSource for &'static [u8] {
+    fn read_at(&mut self, offset: u32, buf: &mut [u8]) -> Result<()> {
+        let start = offset as usize;
+        let data = self
+            .get(start..start + buf.len())
+            .ok_or(anyhow!("font data out of range: {offset}"))?;
+        buf.copy_from_slice(data);
+        Ok(())
+    }
+}
+
+impl FontSource for File {
+    fn read_at(&mut self, offset: u32, buf: &mut [u8]) -> Result<()> {
+        self.seek(SeekFrom::Start(offset as u64))?;
+        self.read_exact(buf)?;
+        Ok(())
+    }
+}
+
+/// 单个字形的点阵
+pub struct Glyph {
+    pub width: u8,
+    /// 绘制后光标向右移动的像素数
+    pub advance: u8,
+    bitmap: Vec<u8>,
+}
+
+impl Glyph {
+    fn pixel(&self, x: u32, y: u32) -> bool {
+        let stride = (self.width as u32).div_ceil(8);
+        let byte = self.bitmap[(y * stride + x / 8) as usize];
+        byte & (0x80 >> (x % 8)) != 0
+    }
+}
+
+pub struct BitmapFont {
+    source: Box<dyn FontSource>,
+    height: u8,
+    glyph_count: u32,
+    /// None 表示字体里没有这个字符
+    cache: HashMap<char, Option<Arc<Glyph>>>,
+    /// 缓存的使用顺序, 最近使用的在后面
+    cache_order: VecDeque<char>,
+}
+
+impl BitmapFont {
+    /// 打开 fat 中的字体文件
+    pub fn open(path: &str) -> Result<Self> {
+        Self::new(Box::new(File::open(path)?))
+    }
+
+    /// 使用编译进固件的字体
+    pub fn from_static(data: &'static [u8]) -> Result<Self> {
+        Self::new(Box::new(data))
+    }
+
+    fn new(mut source: Box<dyn FontSource>) -> Result<Self> {
+        let mut header = [0_u8; FONT_HEADER_LEN as usize];
+        source.read_at(0, &mut header)?;
+        if &header[0..4] != FONT_MAGIC || header[4] != FONT_VERSION || header[5] < 4 {
+            return Err(anyhow!("unsupported font file"));
+        }
+        Ok(Self {
+            source,
+            height: header[5],
+            glyph_count: u32::from_le_bytes(header[8..12].try_into()?),
+            cache: HashMap::new(),
+            cache_order: VecDeque::new(),
+        })
+    }
+
+    /// 行高
+    pub fn height(&self) -> u32 {
+        self.height as u32
+    }
+
+    /// 查找字形, 优先使用缓存
+    pub fn glyph(&mut self, c: char) -> Result<Option<Arc<Glyph>>> {
+        if let Some(glyph) = self.cache.get(&c).cloned() {
+            self.cache_order.retain(|cached| *cached != c);
+            self.cache_order.push_back(c);
+            return Ok(glyph);
+        }
+        let glyph = self.load_glyph(c)?.map(Arc::new);
+        if self.cache_order.len() >= GLYPH_CACHE_MAX {
+            if let Some(oldest) = self.cache_order.pop_front() {
+                self.cache.remove(&oldest);
+            }
+        }
+        self.cache.insert(c, glyph.clone());
+        self.cache_order.push_back(c);
+        Ok(glyph)
+    }
+
+    /// 在索引中二分查找字符, 读出点阵
+    fn load_glyph(&mut self, c: char) -> Result<Option<Glyph>> {
+        let codepoint = c as u32;
+        let (mut low, mut high) = (0, self.glyph_count);
+        let mut entry = [0_u8; FONT_INDEX_ENTRY_LEN as usize];
+        while low < high {
+            let mid = low + (high - low) / 2;
+            self.source
+                .read_at(FONT_HEADER_LEN + mid * FONT_INDEX_ENTRY_LEN, &mut entry)?;
+            let mid_codepoint = u32::from_le_bytes(entry[0..4].try_into()?);
+            if mid_codepoint < codepoint {
+                low = mid + 1;
+            } else if mid_codepoint > codepoint {
+                high = mid;
+            } else {
+                let offset = u32::from_le_bytes(entry[4..8].try_into()?);
+                let mut metrics = [0_u8; 2];
+                self.source.read_at(offset, &mut metrics)?;
+                let stride = (metrics[0] as usize).div_ceil(8);
+                let mut bitmap = vec![0_u8; stride * self.height as usize];
+                self.source.read_at(offset + 2, &mut bitmap)?;
+                return Ok(Some(Glyph {
+                    width: metrics[0],
+                    advance: metrics[1],
+                    bitmap,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    fn missing_advance(&self) -> u32 {
+        (self.height as u32 / 2).max(1)
+    }
+
+    /// 从 position(左上角)开始绘制一行文字, 返回绘制结束后的位置.
+    /// 指定背景色时按字形矩形整块填充, spi 传输更少; 缺失的字符画一个空心方框
+    pub fn draw_text<D>(
+        &mut self,
+        display: &mut D,
+        text: &str,
+        position: Point,
+        color: Rgb565,
+        background: Option<Rgb565>,
+    ) -> Result<Point>
+    where
+        D: DrawTarget<Color = Rgb565>,
+        D::Error: core::fmt::Debug,
+    {
+        let height = self.height as u32;
+        let mut cursor = position;
+        for c in text.chars() {
+            match self.glyph(c)? {
+                Some(glyph) => {
+                    let size = Size::new(glyph.advance as u32, height);
+                    let pixel = |x: u32, y: u32| x < glyph.width as u32 && glyph.pixel(x, y);
+                    match background {
+                        Some(background) => {
+                            let colors =
+                                (0..height).flat_map(|y| {
+                                    (0..size.width).map(move |x| {
+                                        if pixel(x, y) {
+                                            color
+                                        } else {
+                                            background
+                                        }
+                                    })
+                                });
+                            display
+                                .fill_contiguous(&Rectangle::new(cursor, size), colors)
+                                .map_err(|e| anyhow!("draw glyph failed: {:?}", e))?;
+                        }
+                        None => {
+                            let pixels = (0..height).flat_map(|y| {
+                                (0..size.width).filter(move |x| pixel(*x, y)).map(move |x| {
+                                    Pixel(cursor + Point::new(x as i32, y as i32), color)
+                                })
+                            });
+                            display
+                                .draw_iter(pixels)
+                                .map_err(|e| anyhow!("draw glyph failed: {:?}", e))?;
+                        }
+                    }
+                    cursor.x += glyph.advance as i32;
+                }
+                None => {
+                    let width = self.missing_advance();
+                    let pixels = (1..height - 1).flat_map(|y| {
+                        (1..width - 1)
+                            .filter(move |x| {
+                                *x == 1 || *x == width - 2 || y == 1 || y == height - 2
+                            })
+                            .map(move |x| Pixel(cursor + Point::new(x as i32, y as i32), color))
+                    });
+                    display
+                        .draw_iter(pixels)
+                        .map_err(|e| anyhow!("draw glyph failed: {:?}", e))?;
+                    cursor.x += width as i32;
+                }
+            }
+        }
+        Ok(cursor)
+    }
+}
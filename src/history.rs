@@ -0,0 +1,141 @@
+//! 芯片温度的历史记录, 按三种时间范围分别保存平均值, 用于屏幕上的温度曲线.
+//! 每个范围固定保存 `HISTORY_POINTS` 个点, 范围越长每个点平均的时间越长.
+
+use std::collections::VecDeque;
+
+/// 每个时间范围保存的点数
+pub const HISTORY_POINTS: usize = 300;
+
+/// 温度曲线显示的时间范围
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryRange {
+    /// 最近 5 分钟, 每秒一个点
+    #[default]
+    Minutes5,
+    /// 最近 1 小时, 每 12 秒一个点
+    Hour,
+    /// 最近 1 天, 每 288 秒一个点
+    Day,
+}
+
+impl HistoryRange {
+    pub const NAMES: &'static str = "5m 1h 1d";
+    const ALL: [Self; 3] = [Self::Minutes5, Self::Hour, Self::Day];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "5m" => Some(Self::Minutes5),
+            "1h" => Some(Self::Hour),
+            "1d" => Some(Self::Day),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Minutes5 => "5m",
+            Self::Hour => "1h",
+            Self::Day => "1d",
+        }
+    }
+
+    pub fn span_secs(self) -> u64 {
+        match self {
+            Self::Minutes5 => 5 * 60,
+            Self::Hour => 60 * 60,
+            Self::Day => 24 * 60 * 60,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// 下一个范围, 最后一个之后回到第一个
+    pub fn next(self) -> Self {
+        Self::ALL[(self.index() + 1) % Self::ALL.len()]
+    }
+
+    pub fn prev(self) -> Self {
+        Self::ALL[(self.index() + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+}
+
+/// 一个时间范围的数据, 同一个时间段内的读数取平均值作为一个点
+struct Series {
+    bucket_secs: u64,
+    points: VecDeque<f32>,
+    bucket_start: u64,
+    sum: f32,
+    count: u32,
+    /// 每增加一个点加一, 用于判断曲线是否需要重绘
+    seq: u64,
+}
+
+impl Series {
+    fn new(range: HistoryRange) -> Self {
+        Self {
+            bucket_secs: range.span_secs() / HISTORY_POINTS as u64,
+            points: VecDeque::with_capacity(HISTORY_POINTS),
+            bucket_start: 0,
+            sum: 0.0,
+            count: 0,
+            seq: 0,
+        }
+    }
+
+    fn push(&mut self, now: u64, value: f32) {
+        let bucket_start = now - now % self.bucket_secs;
+        if bucket_start != self.bucket_start && self.count > 0 {
+            if self.points.len() == HISTORY_POINTS {
+                self.points.pop_front();
+            }
+            self.points.push_back(self.sum / self.count as f32);
+            self.seq += 1;
+            self.sum = 0.0;
+            self.count = 0;
+        }
+        self.bucket_start = bucket_start;
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+pub struct TemperatureHistory {
+    series: [Series; 3],
+}
+
+impl Default for TemperatureHistory {
+    fn default() -> Self {
+        Self {
+            series: HistoryRange::ALL.map(Series::new),
+        }
+    }
+}
+
+/// 状态日志里只打印点数, 不打印所有数据
+impl core::fmt::Debug for TemperatureHistory {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TemperatureHistory")
+            .field("points", &self.series.each_ref().map(|s| s.points.len()))
+            .finish()
+    }
+}
+
+impl TemperatureHistory {
+    /// 记录一次读数, now 为上电后的秒数
+    pub fn push(&mut self, now: u64, value: f32) {
+        for series in &mut self.series {
+            series.push(now, value);
+        }
+    }
+
+    /// 按时间顺序返回指定范围内的点, 最后一个点是最新的
+    pub fn points(&self, range: HistoryRange) -> Vec<f32> {
+        self.series[range.index()].points.iter().copied().collect()
+    }
+
+    pub fn seq(&self, range: HistoryRange) -> u64 {
+        self.series[range.index()].seq
+    }
+}
@@ -1,11 +1,30 @@
 use crate::board::BoardEsp32State;
-use embedded_svc::http::Method;
+use crate::canvas::{self, DisplayCommand};
+use crate::image_loader;
+use crate::shadow;
+use crate::storage::{self, FS_MOUNT_POINT};
+use embedded_graphics::{geometry::Point, pixelcolor::Rgb565, prelude::RgbColor};
+use embedded_svc::http::{Headers, Method};
 use esp_idf_svc::http::server::EspHttpServer;
-use esp_idf_svc::io::Write;
-use std::fs;
+use esp_idf_svc::http::server::{EspHttpConnection, Request};
+use esp_idf_svc::io::{Read as _, Write};
+use std::fs::{self, File};
+use std::io::Write as _;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 
-const FS_MOUNT_POINT: &str = "/fat";
+/// 上传图片时每次读取并写入文件的长度
+const FILE_CHUNK_SIZE: usize = 1024;
+/// 远程绘制文字的最大长度
+const CANVAS_TEXT_MAX: usize = 256;
+/// 上传图片的最大长度
+const CANVAS_IMAGE_MAX: usize = 512 * 1024;
+/// 上传的图片保存的位置, 每次上传覆盖上一张, 画布中引用上一张的命令会被丢弃
+const CANVAS_IMAGE_NAME: &str = "canvas_upload";
+/// 上传时先写入加了这个前缀的临时文件, 检查通过后再替换正式文件
+const UPLOAD_TEMP_PREFIX: &str = "tmp_";
+/// 等待执行的绘制命令上限, 主循环来不及处理时拒绝新的命令
+const CANVAS_QUEUE_MAX: usize = 16;
 
 pub struct HttpServer<'d> {
     server: EspHttpServer<'d>,
@@ -15,6 +34,7 @@ impl<'d> HttpServer<'d> {
     // 开启http服务
     pub fn new(board: Arc<Mutex<BoardEsp32State>>) -> anyhow::Result<Self, anyhow::Error> {
         let mut server = EspHttpServer::new(&esp_idf_svc::http::server::Configuration::default())?;
+        let board_canvas = Arc::clone(&board);
         let board_state = board.lock().expect("Failed to lock board mutex");
 
         Self::http_server_add_page(&mut server, "/", Self::index_html())?;
@@ -26,6 +46,8 @@ impl<'d> HttpServer<'d> {
         log::info!("http server running");
         let mut httpserver = Self { server };
         httpserver.file_list(FS_MOUNT_POINT.to_string())?;
+        httpserver.display_canvas(board_canvas)?;
+        httpserver.display_screenshot()?;
 
         Ok(httpserver)
     }
@@ -69,6 +91,133 @@ impl<'d> HttpServer<'d> {
         })?;
         Ok(())
     }
+    /// 远程绘制接口, 命令放进队列由主循环执行
+    /// - `POST /api/display/clear?color=RRGGBB`
+    /// - `POST /api/display/text?x=&y=&color=RRGGBB&size=1|2`, 文字放在请求体中
+    /// - `POST /api/display/image?x=&y=`, 请求体为 BMP 或 QOI 图片,
+    ///   RGB565 原始数据需要带上 `w=&h=`, 不指定位置时居中显示
+    fn display_canvas(&mut self, board: Arc<Mutex<BoardEsp32State>>) -> anyhow::Result<()> {
+        let board_clear = Arc::clone(&board);
+        self.server
+            .fn_handler("/api/display/clear", Method::Post, move |req| {
+                let color = match query_param(req.uri(), "color") {
+                    Some(color) => match canvas::parse_color(&color) {
+                        Ok(color) => color,
+                        Err(err) => return bad_request(req, err),
+                    },
+                    None => Rgb565::BLACK,
+                };
+                if !push_display_command(&board_clear, DisplayCommand::Clear(color)) {
+                    req.into_status_response(503)?.write_all(b"display busy")?;
+                    return Ok(());
+                }
+                req.into_ok_response()?.write_all(b"ok")?;
+                Ok::<(), anyhow::Error>(())
+            })?;
+
+        let board_text = Arc::clone(&board);
+        self.server
+            .fn_handler("/api/display/text", Method::Post, move |mut req| {
+                let uri = req.uri().to_string();
+                let position = match query_position(&uri) {
+                    Ok(position) => position.unwrap_or_default(),
+                    Err(err) => return bad_request(req, err),
+                };
+                let color =
+                    match query_param(&uri, "color").map(|color| canvas::parse_color(&color)) {
+                        Some(Ok(color)) => color,
+                        Some(Err(err)) => return bad_request(req, err),
+                        None => Rgb565::WHITE,
+                    };
+                let size = match query_param(&uri, "size")
+                    .as_deref()
+                    .unwrap_or("1")
+                    .parse::<u8>()
+                {
+                    Ok(size @ 1..=2) => size,
+                    _ => return bad_request(req, anyhow::anyhow!("size must be 1 or 2")),
+                };
+                let body = match read_body(&mut req, CANVAS_TEXT_MAX) {
+                    Ok(body) => body,
+                    Err(err) => return bad_request(req, err),
+                };
+                let text = match String::from_utf8(body) {
+                    Ok(text) => text,
+                    Err(err) => return bad_request(req, err.into()),
+                };
+                let command = DisplayCommand::Text {
+                    text,
+                    position,
+                    color,
+                    size,
+                };
+                if !push_display_command(&board_text, command) {
+                    req.into_status_response(503)?.write_all(b"display busy")?;
+                    return Ok(());
+                }
+                req.into_ok_response()?.write_all(b"ok")?;
+                Ok::<(), anyhow::Error>(())
+            })?;
+
+        self.server
+            .fn_handler("/api/display/image", Method::Post, move |mut req| {
+                let uri = req.uri().to_string();
+                let position = match query_position(&uri) {
+                    Ok(position) => position,
+                    Err(err) => return bad_request(req, err),
+                };
+                let name = match upload_image_name(&uri) {
+                    Ok(name) => name,
+                    Err(err) => return bad_request(req, err),
+                };
+                let paths = storage::fat_path(&name).and_then(|path| {
+                    Ok((
+                        path,
+                        storage::fat_path(&format!("{UPLOAD_TEMP_PREFIX}{name}"))?,
+                    ))
+                });
+                let (path, temp) = match paths {
+                    Ok(paths) => paths,
+                    Err(err) => return bad_request(req, err),
+                };
+                // 图片可能很大, 没有 psram 时放不进内存, 边读边写入临时文件.
+                // 检查通过后才替换正式文件, 出错时画布引用的上一张图片保持不变
+                let saved = save_body(&mut req, &temp, CANVAS_IMAGE_MAX)
+                    .and_then(|_| image_loader::image_info(&temp));
+                if let Err(err) = saved {
+                    let _ = fs::remove_file(&temp);
+                    return bad_request(req, err);
+                }
+                if let Err(err) = replace_file(&temp, &path) {
+                    let _ = fs::remove_file(&temp);
+                    return Err(err.into());
+                }
+                if !push_display_command(&board, DisplayCommand::Image { path, position }) {
+                    req.into_status_response(503)?.write_all(b"display busy")?;
+                    return Ok(());
+                }
+                req.into_ok_response()?.write_all(b"ok")?;
+                Ok::<(), anyhow::Error>(())
+            })?;
+        Ok(())
+    }
+
+    /// 屏幕截图, `GET /api/display/screenshot` 返回 16 位 BMP
+    fn display_screenshot(&mut self) -> anyhow::Result<()> {
+        self.server
+            .fn_handler("/api/display/screenshot", Method::Get, |req| {
+                if shadow::screenshot_size().is_none() {
+                    req.into_status_response(503)?
+                        .write_all(b"screenshot not available")?;
+                    return Ok(());
+                }
+                let mut resp = req.into_response(200, None, &[("Content-Type", "image/bmp")])?;
+                shadow::write_bmp(|data| Ok(resp.write_all(data)?))?;
+                Ok::<(), anyhow::Error>(())
+            })?;
+        Ok(())
+    }
+
     fn templated(content: impl AsRef<str>) -> String {
         format!(
             r#"
@@ -95,3 +244,144 @@ impl<'d> HttpServer<'d> {
         Self::templated(format!("mcu temperature: {}", val))
     }
 }
+
+/// 读取请求体, 超过 max 字节时返回错误
+fn read_body(req: &mut Request<&mut EspHttpConnection>, max: usize) -> anyhow::Result<Vec<u8>> {
+    let len = req.content_len().unwrap_or(0) as usize;
+    if len > max {
+        return Err(anyhow::anyhow!("body too large, max {max} bytes"));
+    }
+    let mut body = vec![0_u8; len];
+    let mut read = 0;
+    while read < len {
+        let n = req.read(&mut body[read..])?;
+        if n == 0 {
+            break;
+        }
+        read += n;
+    }
+    body.truncate(read);
+    Ok(body)
+}
+
+/// 把请求体分块写入文件, 超过 max 字节时返回错误
+fn save_body(
+    req: &mut Request<&mut EspHttpConnection>,
+    path: &Path,
+    max: usize,
+) -> anyhow::Result<()> {
+    let len = req.content_len().unwrap_or(0) as usize;
+    if len > max {
+        return Err(anyhow::anyhow!("body too large, max {max} bytes"));
+    }
+    let mut file = File::create(path)?;
+    let mut buf = [0_u8; FILE_CHUNK_SIZE];
+    let mut written = 0;
+    while written < len {
+        let chunk = (len - written).min(FILE_CHUNK_SIZE);
+        let n = req.read(&mut buf[..chunk])?;
+        if n == 0 {
+            break;
+        }
+        file.write_all(&buf[..n])?;
+        written += n;
+    }
+    Ok(())
+}
+
+/// 上传图片保存的文件名. RGB565 原始数据没有文件头, 尺寸 w, h 放在文件名中
+fn upload_image_name(uri: &str) -> anyhow::Result<String> {
+    match (query_param(uri, "w"), query_param(uri, "h")) {
+        (None, None) => Ok(format!("{CANVAS_IMAGE_NAME}.img")),
+        (w, h) => Ok(format!(
+            "{CANVAS_IMAGE_NAME}_{}x{}.raw",
+            parse_image_size(w)?,
+            parse_image_size(h)?
+        )),
+    }
+}
+
+fn parse_image_size(value: Option<String>) -> anyhow::Result<u32> {
+    let value = value.ok_or(anyhow::anyhow!("missing image size"))?;
+    match value.parse() {
+        Ok(size) if (1..=image_loader::IMAGE_SIZE_MAX).contains(&size) => Ok(size),
+        _ => Err(anyhow::anyhow!("invalid image size: {value}")),
+    }
+}
+
+/// 用 from 替换 to. fat 的 rename 不能覆盖已有文件, 失败时先删除 to 再重命名
+fn replace_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    match fs::remove_file(to) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+        _ => fs::rename(from, to),
+    }
+}
+
+/// 返回 400 和错误信息
+fn bad_request(req: Request<&mut EspHttpConnection>, err: anyhow::Error) -> anyhow::Result<()> {
+    req.into_status_response(400)?
+        .write_all(err.to_string().as_bytes())?;
+    Ok(())
+}
+
+/// 从查询参数 x, y 中取出位置, 都没有时返回 None
+fn query_position(uri: &str) -> anyhow::Result<Option<Point>> {
+    match (query_param(uri, "x"), query_param(uri, "y")) {
+        (None, None) => Ok(None),
+        (x, y) => Ok(Some(Point::new(
+            x.as_deref().unwrap_or("0").parse()?,
+            y.as_deref().unwrap_or("0").parse()?,
+        ))),
+    }
+}
+
+/// 绘制命令放进队列, 队列已满时返回 false
+fn push_display_command(board: &Arc<Mutex<BoardEsp32State>>, command: DisplayCommand) -> bool {
+    let mut board_state = board.lock().expect("Failed to lock board mutex");
+    if board_state.display_commands.len() >= CANVAS_QUEUE_MAX {
+        return false;
+    }
+    board_state.display_commands.push_back(command);
+    true
+}
+
+/// 从 uri 中取出查询参数的值并做 url 解码
+fn query_param(uri: &str, key: &str) -> Option<String> {
+    let (_, query) = uri.split_once('?')?;
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| url_decode(v))
+}
+
+/// 解码 `%XX` 和 `+`, 格式错误的 `%` 原样保留
+fn url_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
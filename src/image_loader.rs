@@ -0,0 +1,273 @@
+//! 从 fat 中读取图片并绘制到屏幕, 支持 BMP, QOI 和 RGB565 原始数据.
+//! 图片按行解码, 每解码一行就发送到屏幕, 大图片也不需要整张放进内存.
+//!
+//! RGB565 原始数据没有文件头, 需要在文件名中带上尺寸, 例如 `ferris_86x64.raw`,
+//! 像素为小端, 和 `assets/ferris.raw` 相同.
+
+use anyhow::{anyhow, Result};
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Point, Size},
+    pixelcolor::{raw::RawU16, Rgb565, Rgb888},
+    primitives::Rectangle,
+};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// 支持的最大图片尺寸, 防止错误的文件头申请过大的行缓存
+pub const IMAGE_SIZE_MAX: u32 = 4096;
+
+/// 图片格式和尺寸
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageInfo {
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Bmp,
+    Qoi,
+    Rgb565,
+}
+
+/// 读取图片尺寸, 不解码像素
+pub fn image_info(path: &Path) -> Result<ImageInfo> {
+    let mut file = File::open(path)?;
+    probe(&mut file, path)
+}
+
+/// 从 top_left 开始绘制图片, 超出屏幕的部分会被裁剪
+pub fn draw_image<D>(display: &mut D, path: &Path, top_left: Point) -> Result<ImageInfo>
+where
+    D: DrawTarget<Color = Rgb565>,
+    D::Error: core::fmt::Debug,
+{
+    let mut file = File::open(path)?;
+    let info = probe(&mut file, path)?;
+    let mut draw_row = |y: u32, row: &[Rgb565]| {
+        let area = Rectangle::new(
+            top_left + Point::new(0, y as i32),
+            Size::new(row.len() as u32, 1),
+        );
+        display
+            .fill_contiguous(&area, row.iter().copied())
+            .map_err(|e| anyhow!("draw image row failed: {:?}", e))
+    };
+    match info.format {
+        ImageFormat::Bmp => decode_bmp(&mut file, &mut draw_row)?,
+        ImageFormat::Qoi => decode_qoi(file, info, &mut draw_row)?,
+        ImageFormat::Rgb565 => decode_rgb565(file, info, &mut draw_row)?,
+    }
+    Ok(info)
+}
+
+/// 根据文件头或者文件名判断格式
+fn probe(file: &mut File, path: &Path) -> Result<ImageInfo> {
+    let mut header = [0_u8; 26];
+    let len = file.read(&mut header)?;
+    file.seek(SeekFrom::Start(0))?;
+    let info = if len >= 26 && header.starts_with(b"BM") {
+        let width = i32::from_le_bytes(header[18..22].try_into()?);
+        let height = i32::from_le_bytes(header[22..26].try_into()?);
+        ImageInfo {
+            format: ImageFormat::Bmp,
+            width: width.unsigned_abs(),
+            height: height.unsigned_abs(),
+        }
+    } else if len >= 14 && header.starts_with(b"qoif") {
+        ImageInfo {
+            format: ImageFormat::Qoi,
+            width: u32::from_be_bytes(header[4..8].try_into()?),
+            height: u32::from_be_bytes(header[8..12].try_into()?),
+        }
+    } else {
+        let (width, height) =
+            raw_size_from_name(path).ok_or(anyhow!("unknown image format: {}", path.display()))?;
+        ImageInfo {
+            format: ImageFormat::Rgb565,
+            width,
+            height,
+        }
+    };
+    if info.width == 0
+        || info.height == 0
+        || info.width > IMAGE_SIZE_MAX
+        || info.height > IMAGE_SIZE_MAX
+    {
+        return Err(anyhow!(
+            "invalid image size: {}x{}",
+            info.width,
+            info.height
+        ));
+    }
+    Ok(info)
+}
+
+/// 从 `name_WxH.raw` 形式的文件名中取出尺寸
+pub fn raw_size_from_name(path: &Path) -> Option<(u32, u32)> {
+    let stem = path.file_stem()?.to_str()?;
+    let size = stem.rsplit('_').next()?;
+    let (width, height) = size.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+fn decode_rgb565(
+    file: File,
+    info: ImageInfo,
+    draw_row: &mut impl FnMut(u32, &[Rgb565]) -> Result<()>,
+) -> Result<()> {
+    let mut reader = BufReader::new(file);
+    let mut bytes = vec![0_u8; info.width as usize * 2];
+    let mut row = Vec::with_capacity(info.width as usize);
+    for y in 0..info.height {
+        reader.read_exact(&mut bytes)?;
+        row.clear();
+        row.extend(
+            bytes
+                .chunks_exact(2)
+                .map(|b| Rgb565::from(RawU16::new(u16::from_le_bytes([b[0], b[1]])))),
+        );
+        draw_row(y, &row)?;
+    }
+    Ok(())
+}
+
+/// 支持未压缩的 16/24/32 位 BMP, 以及 16 位 BI_BITFIELDS(RGB565)
+fn decode_bmp(
+    file: &mut File,
+    draw_row: &mut impl FnMut(u32, &[Rgb565]) -> Result<()>,
+) -> Result<()> {
+    let mut header = [0_u8; 54];
+    file.read_exact(&mut header)?;
+    let data_offset = u32::from_le_bytes(header[10..14].try_into()?) as u64;
+    let width = i32::from_le_bytes(header[18..22].try_into()?).unsigned_abs();
+    let raw_height = i32::from_le_bytes(header[22..26].try_into()?);
+    let bpp = u16::from_le_bytes(header[28..30].try_into()?);
+    let compression = u32::from_le_bytes(header[30..34].try_into()?);
+
+    // 16 位时默认为 RGB555, BI_BITFIELDS 且绿色掩码为 6 位时为 RGB565
+    let mut rgb565 = false;
+    match (bpp, compression) {
+        (24 | 32, 0) | (16, 0) => {}
+        (16, 3) => {
+            // 掩码紧跟在 40 字节的信息头后面, V4/V5 头在相同位置
+            let mut masks = [0_u8; 12];
+            file.seek(SeekFrom::Start(54))?;
+            file.read_exact(&mut masks)?;
+            rgb565 = u32::from_le_bytes(masks[4..8].try_into()?) == 0x07e0;
+        }
+        _ => {
+            return Err(anyhow!(
+                "unsupported bmp: {bpp} bpp, compression {compression}"
+            ))
+        }
+    }
+
+    let height = raw_height.unsigned_abs();
+    let bytes_per_pixel = bpp as usize / 8;
+    // 每行按 4 字节对齐
+    let stride = (width as usize * bytes_per_pixel).div_ceil(4) * 4;
+    let mut bytes = vec![0_u8; stride];
+    let mut row = Vec::with_capacity(width as usize);
+    for y in 0..height {
+        // 高度为正时数据从最后一行开始存放
+        let file_row = if raw_height > 0 { height - 1 - y } else { y };
+        file.seek(SeekFrom::Start(
+            data_offset + file_row as u64 * stride as u64,
+        ))?;
+        file.read_exact(&mut bytes)?;
+        row.clear();
+        row.extend(
+            bytes[..width as usize * bytes_per_pixel]
+                .chunks_exact(bytes_per_pixel)
+                .map(|p| match bytes_per_pixel {
+                    2 => {
+                        let value = u16::from_le_bytes([p[0], p[1]]);
+                        if rgb565 {
+                            Rgb565::from(RawU16::new(value))
+                        } else {
+                            let (r, g, b) =
+                                ((value >> 10) & 0x1f, (value >> 5) & 0x1f, value & 0x1f);
+                            Rgb565::new(r as u8, (g << 1 | g >> 4) as u8, b as u8)
+                        }
+                    }
+                    _ => Rgb565::from(Rgb888::new(p[2], p[1], p[0])),
+                }),
+        );
+        draw_row(y, &row)?;
+    }
+    Ok(())
+}
+
+/// 流式 QOI 解码, 参考 https://qoiformat.org/qoi-specification.pdf
+fn decode_qoi(
+    file: File,
+    info: ImageInfo,
+    draw_row: &mut impl FnMut(u32, &[Rgb565]) -> Result<()>,
+) -> Result<()> {
+    let mut reader = BufReader::new(file);
+    let mut header = [0_u8; 14];
+    reader.read_exact(&mut header)?;
+    let mut read_u8 = || -> Result<u8> {
+        let mut byte = [0_u8; 1];
+        reader.read_exact(&mut byte)?;
+        Ok(byte[0])
+    };
+
+    let mut index = [[0_u8; 4]; 64];
+    let mut pixel = [0_u8, 0, 0, 255];
+    let mut run = 0_u32;
+    let mut row = Vec::with_capacity(info.width as usize);
+    for y in 0..info.height {
+        row.clear();
+        for _ in 0..info.width {
+            if run > 0 {
+                run -= 1;
+            } else {
+                let tag = read_u8()?;
+                match tag {
+                    0xfe => {
+                        pixel[0] = read_u8()?;
+                        pixel[1] = read_u8()?;
+                        pixel[2] = read_u8()?;
+                    }
+                    0xff => {
+                        for channel in pixel.iter_mut() {
+                            *channel = read_u8()?;
+                        }
+                    }
+                    _ => match tag >> 6 {
+                        0b00 => pixel = index[tag as usize & 0x3f],
+                        0b01 => {
+                            pixel[0] = pixel[0].wrapping_add((tag >> 4 & 0x03).wrapping_sub(2));
+                            pixel[1] = pixel[1].wrapping_add((tag >> 2 & 0x03).wrapping_sub(2));
+                            pixel[2] = pixel[2].wrapping_add((tag & 0x03).wrapping_sub(2));
+                        }
+                        0b10 => {
+                            let dg = (tag & 0x3f).wrapping_sub(32);
+                            let next = read_u8()?;
+                            pixel[0] =
+                                pixel[0].wrapping_add(dg.wrapping_sub(8).wrapping_add(next >> 4));
+                            pixel[1] = pixel[1].wrapping_add(dg);
+                            pixel[2] =
+                                pixel[2].wrapping_add(dg.wrapping_sub(8).wrapping_add(next & 0x0f));
+                        }
+                        _ => run = (tag & 0x3f) as u32,
+                    },
+                }
+                let hash = (pixel[0] as usize * 3
+                    + pixel[1] as usize * 5
+                    + pixel[2] as usize * 7
+                    + pixel[3] as usize * 11)
+                    % 64;
+                index[hash] = pixel;
+            }
+            row.push(Rgb565::from(Rgb888::new(pixel[0], pixel[1], pixel[2])));
+        }
+        draw_row(y, &row)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,125 @@
+//! 把 `log` 输出同时保存到环形缓冲区, 没有串口时也能在屏幕上查看最近的日志.
+//! 日志仍然通过 `EspLogger` 输出到串口
+
+use crate::ui;
+use embedded_graphics::{draw_target::DrawTarget, pixelcolor::Rgb565};
+use esp_idf_svc::log::EspLogger;
+use log::{Level, Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+
+/// 缓冲区保存的日志条数
+const LOG_LINES_MAX: usize = 64;
+/// 单条日志保存的最大字符数
+const LOG_LINE_CHARS_MAX: usize = 160;
+
+static LOG_CONSOLE: OnceLock<LogConsole> = OnceLock::new();
+
+struct LogLines {
+    lines: VecDeque<(Level, String)>,
+    /// 每写入一条加一, 用于判断屏幕是否需要刷新
+    seq: u64,
+}
+
+pub struct LogConsole {
+    inner: EspLogger,
+    lines: Mutex<LogLines>,
+}
+
+impl LogConsole {
+    fn new() -> Self {
+        Self {
+            inner: EspLogger::new(),
+            lines: Mutex::new(LogLines {
+                lines: VecDeque::with_capacity(LOG_LINES_MAX),
+                seq: 0,
+            }),
+        }
+    }
+
+    /// 替代 `EspLogger::initialize_default`, 安装带缓冲区的日志
+    pub fn initialize() {
+        let logger = LOG_CONSOLE.get_or_init(Self::new);
+        if log::set_logger(logger).is_ok() {
+            logger.inner.initialize();
+        }
+    }
+
+    /// 取出最近的日志, seq 和上次相同时返回 None
+    fn snapshot(&self, last_seq: u64, count: usize) -> Option<(u64, Vec<(Level, String)>)> {
+        let lines = self.lines.lock().ok()?;
+        if lines.seq == last_seq {
+            return None;
+        }
+        let skip = lines.lines.len().saturating_sub(count);
+        Some((lines.seq, lines.lines.iter().skip(skip).cloned().collect()))
+    }
+}
+
+impl Log for LogConsole {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        self.inner.log(record);
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut text = record.args().to_string();
+        if let Some((index, _)) = text.char_indices().nth(LOG_LINE_CHARS_MAX) {
+            text.truncate(index);
+        }
+        // 日志里可能有其他线程持有锁时打印的内容, 拿不到锁就丢弃, 不能阻塞
+        if let Ok(mut lines) = self.lines.try_lock() {
+            if lines.lines.len() == LOG_LINES_MAX {
+                lines.lines.pop_front();
+            }
+            lines.lines.push_back((record.level(), text));
+            lines.seq += 1;
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// 在屏幕上以滚动终端的形式显示日志, 颜色区分日志等级
+#[derive(Default)]
+pub struct LogConsoleView {
+    drawn_seq: Option<u64>,
+    /// 屏幕上已经显示的行, None 表示需要清屏重绘
+    drawn_lines: Option<Vec<ui::LogLine>>,
+}
+
+impl LogConsoleView {
+    /// 下次绘制时重绘整个屏幕
+    pub fn invalidate(&mut self) {
+        self.drawn_seq = None;
+        self.drawn_lines = None;
+    }
+
+    /// 有新日志时只重绘内容变化的行
+    pub fn draw<D>(&mut self, display: &mut D) -> anyhow::Result<()>
+    where
+        D: DrawTarget<Color = Rgb565>,
+        D::Error: core::fmt::Debug,
+    {
+        let Some(console) = LOG_CONSOLE.get() else {
+            return Ok(());
+        };
+        let (_, rows) = ui::log_screen_size(display);
+        let last_seq = self.drawn_seq.unwrap_or(u64::MAX);
+        let Some((seq, records)) = console.snapshot(last_seq, rows) else {
+            return Ok(());
+        };
+        let lines = ui::log_screen_lines(display, &records);
+        // 绘制失败时屏幕内容未知, 下次清屏重绘
+        let previous = self.drawn_lines.take();
+        ui::draw_log_lines(display, &lines, previous.as_deref())?;
+        self.drawn_seq = Some(seq);
+        self.drawn_lines = Some(lines);
+        Ok(())
+    }
+}
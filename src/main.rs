@@ -1,35 +1,168 @@
+#[cfg_attr(not(feature = "enable_ble_central"), allow(dead_code))]
+mod ble_central;
+mod ble_file;
+#[cfg_attr(not(feature = "enable_ble_hid"), allow(dead_code))]
+mod ble_hid;
+mod ble_ota;
+mod ble_uart;
 mod board;
+mod boot;
+mod button;
+mod canvas;
+#[cfg(feature = "display")]
+mod chart;
+#[cfg_attr(not(feature = "display"), allow(dead_code))]
+mod clock;
+mod console;
+mod dashboard;
 mod display;
+mod font;
+#[cfg_attr(not(feature = "display"), allow(dead_code))]
+mod history;
 mod http_server;
+mod image_loader;
+mod log_console;
+#[cfg(feature = "display")]
+mod pages;
+#[cfg_attr(not(feature = "display"), allow(dead_code))]
+mod qr;
+#[cfg(feature = "display")]
+mod screen;
+#[cfg_attr(not(feature = "display"), allow(dead_code))]
+mod screen_power;
+mod shadow;
+mod sprite;
+mod storage;
+mod telemetry;
+mod ui;
+#[cfg_attr(not(feature = "display"), allow(dead_code))]
+mod widget;
 
 use crate::board::BoardEsp32State;
 use board::BspEsp32S3CoreBoard;
 use esp_idf_svc::hal::peripherals::Peripherals;
+use esp_idf_svc::sntp::EspSntp;
+#[cfg(feature = "display")]
+use esp_idf_svc::timer::EspTaskTimerService;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 fn main() -> anyhow::Result<()> {
     esp_idf_svc::sys::link_patches();
-    esp_idf_svc::log::EspLogger::initialize_default();
+    log_console::LogConsole::initialize();
+    boot::install_panic_hook();
 
     let peripherals = Peripherals::take()?;
+    #[cfg(not(feature = "use_psram_framebuffer"))]
     let mut display_buffer = [0_u8; 512];
-    let mut board = BspEsp32S3CoreBoard::new(peripherals, &mut display_buffer)?;
-    let board_state = BoardEsp32State::default();
+    // 帧缓存按矩形整块刷新, 使用更大的发送缓冲区减少 spi 传输次数
+    #[cfg(feature = "use_psram_framebuffer")]
+    let mut display_buffer = vec![0_u8; board::DISPLAY_DMA_BUFFER_SIZE];
+    // 初始化出错时 new 已经在屏幕上显示了错误信息
+    let mut board = match BspEsp32S3CoreBoard::new(peripherals, &mut display_buffer) {
+        Ok(board) => board,
+        Err(err) => boot::restart_after_error(&err),
+    };
+    if let Err(err) = run(&mut board) {
+        #[cfg(feature = "display")]
+        board.display_fatal_error(&err);
+        boot::restart_after_error(&err);
+    }
+    Ok(())
+}
+
+/// 启动各个服务并运行主循环, 只在出错时返回
+fn run(board: &mut BspEsp32S3CoreBoard) -> anyhow::Result<()> {
+    let board_state = BoardEsp32State {
+        fs_ready: board.get_fs_init(),
+        #[cfg(feature = "display")]
+        display_config: Some(board.display_panel()),
+        ..Default::default()
+    };
     // 有需要的话可以在线程结束后回收
     let board_http = Arc::new(Mutex::new(board_state));
     let board_ble = Arc::clone(&board_http);
     let board_state = Arc::clone(&board_http);
+    board.boot_stage("ble");
     let _ble_server_handle = BspEsp32S3CoreBoard::ble_server_start(board_ble)?;
+    board.boot_stage("http server");
     let _http_server_handle = http_server::HttpServer::new(board_http)?;
+    #[cfg(feature = "enable_ble_central")]
+    let _ble_central_handle =
+        ble_central::ble_central_start(Arc::clone(&board_state), ble_central::default_sensors())?;
+    // 用保存的 wifi 名字和密码连接, 断开后自动重连, 连接后 sntp 自动同步系统时间.
+    // 连接和 sntp 失败不影响其他功能, 时钟显示未同步
+    let _wifi_reconnect = board.wifi_reconnect_start()?;
+    if let Err(err) = board.wifi_connect() {
+        log::warn!("wifi connect failed: {err:?}");
+    }
+    let _sntp = EspSntp::new_default()
+        .inspect_err(|err| log::warn!("sntp start failed: {err:?}"))
+        .ok();
+    // 时钟画面由定时器通知刷新, 不在主循环中每次检查时间
+    #[cfg(feature = "display")]
+    let _clock_timer = {
+        let timer = EspTaskTimerService::new()?.timer(clock::tick)?;
+        timer.every(clock::CLOCK_TICK)?;
+        timer
+    };
+    boot::enter(boot::STAGE_RUNNING);
+    // 启动成功, 通过 ota 升级的固件不再回滚
+    ble_ota::mark_app_valid();
     let mut loop_times = 0;
+    #[cfg(feature = "display")]
+    let mut screen = screen::ScreenManager::new();
     #[cfg(feature = "use_ws2812")]
     let mut hue: u8 = 0;
+    let mut buttons = button::Buttons::new();
+    #[cfg(feature = "display")]
+    let ui_buttons = buttons.subscribe();
+    // 唤醒屏幕和双击关闭屏幕的按键在松开前不作为界面输入
+    #[cfg(feature = "display")]
+    let mut swallowed = [false; button::BUTTON_COUNT];
+    #[cfg(feature = "enable_ble_hid")]
+    let hid_buttons = buttons.subscribe();
     loop {
         thread::sleep(Duration::from_millis(50));
         let mut state = board_state.lock().expect("Could not lock board state");
         state.current_mcu_temperature = board.get_mcu_temperature()?;
+        let temperature = state.current_mcu_temperature;
+        state
+            .temperature_history
+            .push(console::uptime_secs(), temperature);
+        buttons.poll(board.buttons_pressed()?, Instant::now());
+        // 按下任意按键唤醒屏幕, 双击返回键关闭屏幕, 长按返回键进入或退出多页面界面,
+        // 其他短按和长按只交给处理输入的画面
+        #[cfg(feature = "display")]
+        for event in ui_buttons.try_iter() {
+            if event.action == button::ButtonAction::Press {
+                swallowed[event.button] = screen.is_asleep();
+                state.display_power = Some(screen_power::PowerRequest::Wake);
+            }
+            if event.sleeps_display() {
+                swallowed[event.button] = true;
+                state.display_power = Some(screen_power::PowerRequest::Sleep);
+            }
+            if event.toggles_ui() && !swallowed[event.button] {
+                state.toggle_ui();
+            }
+            if let Some(input) = event.input_event().filter(|_| !swallowed[event.button]) {
+                state.push_ui_event(input);
+            }
+        }
+        // 按下时通过 ble HID 发送对应的按键, 多页面界面和温度曲线显示时按键只用于界面
+        #[cfg(feature = "enable_ble_hid")]
+        for event in hid_buttons.try_iter() {
+            if state.display_screen.takes_input() {
+                continue;
+            }
+            if event.action == button::ButtonAction::Press {
+                ble_hid::push_key(&mut state, ble_hid::BUTTON_KEYS[event.button]);
+            }
+        }
+        #[cfg(feature = "display")]
+        screen.update(board, &mut state, loop_times)?;
         #[cfg(feature = "use_ws2812")]
         {
             hue = hue.wrapping_add(10);
@@ -0,0 +1,128 @@
+//! 由控件组成的多页面界面: 状态, 设置和信息页面.
+//! 只负责页面的组成和显示内容, 设置项被选中后怎么修改板子状态由屏幕管理处理.
+
+use crate::dashboard::DashboardData;
+use crate::widget::{Page, Ui, Widget};
+
+pub const STATUS_PAGE: &str = "status";
+pub const SETTINGS_PAGE: &str = "settings";
+pub const INFO_PAGE: &str = "info";
+/// 设置页面的列表
+pub const SETTINGS_LIST: &str = "settings";
+
+/// 设置页面的选项, 顺序和列表中的顺序一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingItem {
+    Rotation,
+    Mirror,
+    Interval,
+    Exit,
+}
+
+impl SettingItem {
+    pub const ALL: [Self; 4] = [Self::Rotation, Self::Mirror, Self::Interval, Self::Exit];
+}
+
+/// 设置页面显示的当前值
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+    /// 屏幕旋转角度, None 表示没有屏幕参数
+    pub rotation: Option<u16>,
+    pub mirrored: bool,
+    pub interval_ms: u64,
+}
+
+/// 信息页面显示的内容
+#[derive(Debug, Clone, PartialEq)]
+pub struct Info {
+    pub version: &'static str,
+    pub panel: String,
+    pub free_heap: u32,
+    pub ip: Option<std::net::Ipv4Addr>,
+}
+
+pub fn build() -> Ui {
+    let status = Page::new(STATUS_PAGE, "Status")
+        .with("temp", Widget::label(""))
+        .with("ble", Widget::label(""))
+        .with("rssi", Widget::label(""))
+        .with("uptime", Widget::label(""))
+        .with("fat", Widget::label(""))
+        .with("fat_bar", Widget::progress_bar(0));
+    let settings =
+        Page::new(SETTINGS_PAGE, "Settings").with(SETTINGS_LIST, Widget::list(Vec::new()));
+    let info = Page::new(INFO_PAGE, "Info")
+        .with("version", Widget::label(""))
+        .with("panel", Widget::label(""))
+        .with("heap", Widget::label(""))
+        .with("ip", Widget::label(""));
+    Ui::new(vec![status, settings, info])
+}
+
+pub fn update_status(ui: &mut Ui, data: &DashboardData) {
+    let Some(page) = ui.page_mut(STATUS_PAGE) else {
+        return;
+    };
+    page.set_text("temp", &format!("Temp: {:.1} C", data.temperature));
+    page.set_text("ble", &format!("BLE:  {} conn", data.ble_connections));
+    let rssi = data
+        .rssi
+        .map(|rssi| format!("RSSI: {rssi} dBm"))
+        .unwrap_or_else(|| "RSSI: -".to_string());
+    page.set_text("rssi", &rssi);
+    page.set_text(
+        "uptime",
+        &format!(
+            "Up:   {}d {:02}:{:02}:{:02}",
+            data.uptime / 86400,
+            data.uptime / 3600 % 24,
+            data.uptime / 60 % 60,
+            data.uptime % 60
+        ),
+    );
+    match data.fat_usage {
+        Some((used, total)) if total > 0 => {
+            page.set_text("fat", &format!("FAT:  {}K/{}K", used / 1024, total / 1024));
+            page.set_progress("fat_bar", (used * 100 / total) as u8);
+        }
+        _ => {
+            page.set_text("fat", "FAT:  -");
+            page.set_progress("fat_bar", 0);
+        }
+    }
+}
+
+pub fn update_settings(ui: &mut Ui, settings: &Settings) {
+    let Some(page) = ui.page_mut(SETTINGS_PAGE) else {
+        return;
+    };
+    let items = SettingItem::ALL
+        .iter()
+        .map(|item| match item {
+            SettingItem::Rotation => match settings.rotation {
+                Some(rotation) => format!("Rotation: {rotation}"),
+                None => "Rotation: -".to_string(),
+            },
+            SettingItem::Mirror => {
+                format!("Mirror: {}", if settings.mirrored { "on" } else { "off" })
+            }
+            SettingItem::Interval => format!("Interval: {} ms", settings.interval_ms),
+            SettingItem::Exit => "Exit to dashboard".to_string(),
+        })
+        .collect();
+    page.set_items(SETTINGS_LIST, items);
+}
+
+pub fn update_info(ui: &mut Ui, info: &Info) {
+    let Some(page) = ui.page_mut(INFO_PAGE) else {
+        return;
+    };
+    page.set_text("version", &format!("FW: {}", info.version));
+    page.set_text("panel", &format!("LCD: {}", info.panel));
+    page.set_text("heap", &format!("Heap: {}K", info.free_heap / 1024));
+    let ip = info
+        .ip
+        .map(|ip| format!("IP: {ip}"))
+        .unwrap_or_else(|| "IP: -".to_string());
+    page.set_text("ip", &ip);
+}
@@ -0,0 +1,197 @@
+//! 在屏幕上显示二维码, 例如网页地址和 wifi 连接信息.
+//! 编码使用 qrcodegen, 这里根据屏幕大小选择纠错等级和版本: 优先使用高纠错等级,
+//! 但模块太小时手机不容易识别, 所以模块小于 `PREFERRED_MODULE_PX` 时降低纠错等级.
+
+use anyhow::anyhow;
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Point, Size},
+    mono_font::{
+        ascii::{FONT_10X20, FONT_6X10},
+        MonoTextStyle,
+    },
+    pixelcolor::Rgb565,
+    prelude::RgbColor,
+    primitives::Rectangle,
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+    Drawable,
+};
+use qrcodegen::{QrCode, QrCodeEcc};
+
+/// 二维码四周空白的模块数, 标准要求 4 个, 屏幕四周本身就是空白所以少留一些
+const QUIET_ZONE: u32 = 2;
+/// 希望每个模块至少占的像素数
+const PREFERRED_MODULE_PX: u32 = 4;
+/// 纠错等级从高到低尝试
+const ECC_LEVELS: [QrCodeEcc; 4] = [
+    QrCodeEcc::High,
+    QrCodeEcc::Quartile,
+    QrCodeEcc::Medium,
+    QrCodeEcc::Low,
+];
+
+/// 二维码屏幕显示的内容
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum QrContent {
+    /// 板子网页的地址
+    #[default]
+    WebUi,
+    /// 加入板子所在 wifi 的连接字符串
+    Wifi,
+    Text(String),
+}
+
+/// 编码后的二维码和每个模块的像素数
+pub struct QrLayout {
+    code: QrCode,
+    module_px: u32,
+}
+
+impl QrLayout {
+    /// 选择在 side 像素的正方形内能显示的最高纠错等级
+    pub fn fit(text: &str, side: u32) -> anyhow::Result<Self> {
+        let mut best: Option<Self> = None;
+        for ecl in ECC_LEVELS {
+            let Ok(code) = QrCode::encode_text(text, ecl) else {
+                continue;
+            };
+            let module_px = side / (code.size() as u32 + 2 * QUIET_ZONE);
+            if module_px >= PREFERRED_MODULE_PX {
+                return Ok(Self { code, module_px });
+            }
+            // 纠错等级越低版本越小, 模块可以更大
+            if best.as_ref().is_none_or(|best| module_px > best.module_px) {
+                best = Some(Self { code, module_px });
+            }
+        }
+        best.filter(|layout| layout.module_px > 0)
+            .ok_or(anyhow!("text too long for qr code on this display"))
+    }
+
+    /// 包括四周空白的边长
+    pub fn side(&self) -> u32 {
+        (self.code.size() as u32 + 2 * QUIET_ZONE) * self.module_px
+    }
+
+    pub fn version(&self) -> u8 {
+        self.code.version().value()
+    }
+
+    pub fn ecc(&self) -> QrCodeEcc {
+        self.code.error_correction_level()
+    }
+
+    /// 左上角为 top_left 绘制, 包括白色的空白区域
+    pub fn draw<D>(&self, display: &mut D, top_left: Point) -> anyhow::Result<()>
+    where
+        D: DrawTarget<Color = Rgb565>,
+        D::Error: core::fmt::Debug,
+    {
+        let side = self.side();
+        display
+            .fill_solid(
+                &Rectangle::new(top_left, Size::new(side, side)),
+                Rgb565::WHITE,
+            )
+            .map_err(|e| anyhow!("draw qr code failed: {:?}", e))?;
+        let origin = top_left + Point::new_equal((QUIET_ZONE * self.module_px) as i32);
+        let module = self.module_px as i32;
+        let size = self.code.size();
+        // 每行连续的黑色模块合并成一个矩形
+        for y in 0..size {
+            let mut x = 0;
+            while x < size {
+                if !self.code.get_module(x, y) {
+                    x += 1;
+                    continue;
+                }
+                let start = x;
+                while x < size && self.code.get_module(x, y) {
+                    x += 1;
+                }
+                let run = Rectangle::new(
+                    origin + Point::new(start * module, y * module),
+                    Size::new(((x - start) * module) as u32, self.module_px),
+                );
+                display
+                    .fill_solid(&run, Rgb565::BLACK)
+                    .map_err(|e| anyhow!("draw qr code failed: {:?}", e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// wifi 连接字符串, 特殊字符需要转义
+pub fn wifi_join_string(ssid: &str, password: &str) -> String {
+    let escape = |value: &str| {
+        value.chars().fold(String::new(), |mut out, c| {
+            if matches!(c, '\\' | ';' | ',' | ':' | '"') {
+                out.push('\\');
+            }
+            out.push(c);
+            out
+        })
+    };
+    if password.is_empty() {
+        format!("WIFI:T:nopass;S:{};;", escape(ssid))
+    } else {
+        format!("WIFI:T:WPA;S:{};P:{};;", escape(ssid), escape(password))
+    }
+}
+
+/// 整屏显示二维码, 下方显示说明文字. text 为空时只显示说明文字
+pub fn draw_qr_screen<D>(display: &mut D, text: &str, caption: &str) -> anyhow::Result<()>
+where
+    D: DrawTarget<Color = Rgb565>,
+    D::Error: core::fmt::Debug,
+{
+    let bounds = display.bounding_box();
+    let font = if bounds.size.height >= 240 {
+        &FONT_10X20
+    } else {
+        &FONT_6X10
+    };
+    display
+        .clear(Rgb565::WHITE)
+        .map_err(|e| anyhow!("clear display failed: {:?}", e))?;
+    let caption_height = font.character_size.height + 4;
+    let caption_top = if text.is_empty() {
+        bounds.center().y - caption_height as i32 / 2
+    } else {
+        let side = bounds
+            .size
+            .width
+            .min(bounds.size.height.saturating_sub(caption_height));
+        let layout = QrLayout::fit(text, side)?;
+        let top_left = bounds.top_left
+            + Point::new(
+                (bounds.size.width - layout.side()) as i32 / 2,
+                (bounds.size.height - caption_height - layout.side()) as i32 / 2,
+            );
+        layout.draw(display, top_left)?;
+        log::info!(
+            "qr code version {} ecc {:?}, {} px per module",
+            layout.version(),
+            layout.ecc(),
+            layout.module_px
+        );
+        top_left.y + layout.side() as i32
+    };
+
+    // 说明文字超出屏幕宽度时截断
+    let columns = (bounds.size.width / font.character_size.width) as usize;
+    let caption: String = caption.chars().take(columns).collect();
+    Text::with_text_style(
+        &caption,
+        Point::new(bounds.center().x, caption_top),
+        MonoTextStyle::new(font, Rgb565::BLACK),
+        TextStyleBuilder::new()
+            .alignment(Alignment::Center)
+            .baseline(Baseline::Top)
+            .build(),
+    )
+    .draw(display)
+    .map_err(|e| anyhow!("draw qr caption failed: {:?}", e))?;
+    Ok(())
+}
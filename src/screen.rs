@@ -0,0 +1,388 @@
+//! 屏幕内容管理, 由主循环调用. 根据 board 状态决定显示哪个画面, 以及哪些区域需要刷新
+
+use crate::board::{BoardEsp32State, BspEsp32S3CoreBoard};
+use crate::canvas::Canvas;
+use crate::chart;
+use crate::clock::{self, ClockSettings, LocalTime};
+use crate::dashboard::Dashboard;
+use crate::display::DisplayScreen;
+use crate::font::BitmapFont;
+use crate::history::HistoryRange;
+use crate::log_console::LogConsoleView;
+use crate::pages::{self, SettingItem};
+use crate::qr::{self, QrContent};
+use crate::screen_power::{PowerRequest, ScreenPower};
+use crate::sprite::Animation;
+use crate::ui;
+use crate::widget::{InputEvent, Ui, UiAction};
+use anyhow::{anyhow, Result};
+use embedded_graphics::geometry::Dimensions;
+use esp_idf_svc::sys;
+use std::time::{Duration, Instant};
+
+/// 状态面板刷新间隔, 主循环 50ms 一次
+const DASHBOARD_REFRESH_LOOPS: u32 = 20;
+/// 日志画面刷新间隔
+const LOG_REFRESH_LOOPS: u32 = 10;
+
+/// 界面使用的中文字体, 不存在时使用编译进固件的字体或英文界面
+const UI_FONT_PATH: &str = "/fat/fonts/ui16.bfnt";
+/// 编译进固件的界面字体, 用 `tools/mkfont.py` 生成
+#[cfg(feature = "embedded_ui_font")]
+const EMBEDDED_UI_FONT: &[u8] = include_bytes!("../assets/ui16.bfnt");
+
+pub struct ScreenManager {
+    /// 当前屏幕上的画面, None 表示需要整屏重绘
+    shown_screen: Option<DisplayScreen>,
+    shown_passkey: Option<u32>,
+    /// 屏幕上温度曲线的范围和数据序号, 有新数据时重绘
+    shown_chart: Option<(HistoryRange, u64)>,
+    /// 屏幕上二维码编码的内容, ip 或 wifi 变化时重绘
+    shown_qr: Option<String>,
+    /// 时钟画面的设置和日期, 变化时整屏重绘
+    shown_clock: Option<(ClockSettings, Option<(i32, u8, u8)>)>,
+    /// 表盘上的时间, 变化时只重绘表盘
+    shown_clock_time: Option<LocalTime>,
+    dashboard: Dashboard,
+    log_view: LogConsoleView,
+    canvas: Canvas,
+    pages: Ui,
+    font: Option<BitmapFont>,
+    power: ScreenPower,
+    /// 状态面板上的 ferris 动画
+    ferris: Option<Animation>,
+}
+
+impl ScreenManager {
+    pub fn new() -> Self {
+        let font = match BitmapFont::open(UI_FONT_PATH) {
+            Ok(font) => Some(font),
+            #[cfg(feature = "embedded_ui_font")]
+            Err(err) => {
+                log::info!("ui font not loaded, use embedded font: {:?}", err);
+                BitmapFont::from_static(EMBEDDED_UI_FONT)
+                    .inspect_err(|err| log::warn!("embedded ui font invalid: {:?}", err))
+                    .ok()
+            }
+            #[cfg(not(feature = "embedded_ui_font"))]
+            Err(err) => {
+                log::info!("ui font not loaded, use ascii font: {:?}", err);
+                None
+            }
+        };
+        let ferris = match ui::ferris_animation() {
+            Ok(ferris) => Some(ferris),
+            Err(err) => {
+                log::warn!("ferris animation not loaded: {:?}", err);
+                None
+            }
+        };
+        Self {
+            shown_screen: None,
+            shown_passkey: None,
+            shown_chart: None,
+            shown_qr: None,
+            shown_clock: None,
+            shown_clock_time: None,
+            dashboard: Dashboard::default(),
+            log_view: LogConsoleView::default(),
+            canvas: Canvas::default(),
+            pages: pages::build(),
+            font,
+            power: ScreenPower::default(),
+            ferris,
+        }
+    }
+
+    pub fn is_asleep(&self) -> bool {
+        self.power.is_asleep()
+    }
+
+    /// 屏幕内容被覆盖后调用, 下次更新时整屏重绘
+    pub fn invalidate(&mut self) {
+        self.shown_screen = None;
+    }
+
+    pub fn update(
+        &mut self,
+        board: &mut BspEsp32S3CoreBoard,
+        state: &mut BoardEsp32State,
+        loop_times: u32,
+    ) -> Result<()> {
+        // 屏幕参数被修改后重新配置屏幕, 之前绘制的内容需要重新绘制
+        if let Some(panel) = state
+            .display_config
+            .filter(|panel| *panel != board.display_panel())
+        {
+            if let Err(err) = board.display_configure(panel) {
+                log::error!("display configure failed: {:?}", err);
+                state.display_config = Some(board.display_panel());
+            }
+            self.invalidate();
+        }
+        // 重新初始化时 spi 出错, 屏幕已经不可用, 其他功能继续运行
+        if !board.has_display() {
+            return Ok(());
+        }
+
+        // 切换画面和需要显示的内容也算作操作, 唤醒屏幕. 明确要求睡眠时不唤醒
+        let mut request = state.display_power.take();
+        if request.is_none()
+            && (self.shown_screen != Some(state.display_screen)
+                || state.display_refresh
+                || state.ble_pairing_passkey.is_some()
+                || !state.display_commands.is_empty()
+                || !state.ui_events.is_empty())
+        {
+            request = Some(PowerRequest::Wake);
+        }
+        if let Some(sleep) = self
+            .power
+            .poll(request, state.display_timeout, Instant::now())
+        {
+            if let Err(err) = board.display_set_sleep(sleep) {
+                log::warn!("display sleep failed: {:?}", err);
+            }
+        }
+        // 睡眠时屏幕内容保留, 不绘制, 唤醒后按当前状态继续更新
+        if self.power.is_asleep() {
+            return Ok(());
+        }
+
+        // 配对时切回状态面板显示配对码
+        if state.ble_pairing_passkey.is_some() {
+            state.display_screen = DisplayScreen::Dashboard;
+        }
+
+        if state.display_refresh {
+            state.display_refresh = false;
+            self.invalidate();
+        }
+
+        // 有远程绘制命令时切换到画布
+        if !state.display_commands.is_empty() && state.ble_pairing_passkey.is_none() {
+            state.display_screen = DisplayScreen::Canvas;
+        }
+
+        let mut refresh = loop_times % DASHBOARD_REFRESH_LOOPS == 0;
+        if self.shown_screen != Some(state.display_screen) {
+            match state.display_screen {
+                DisplayScreen::Dashboard => ui::draw_home(board.display_mut()?)?,
+                DisplayScreen::Log
+                | DisplayScreen::Ui
+                | DisplayScreen::Chart
+                | DisplayScreen::Qr
+                | DisplayScreen::Clock => {}
+                DisplayScreen::Canvas => {
+                    if let Err(err) = self.canvas.redraw(board.display_mut()?, self.font.as_mut()) {
+                        log::warn!("draw canvas failed: {:?}", err);
+                    }
+                }
+                DisplayScreen::Image => {
+                    if let Err(err) =
+                        ui::draw_image_screen(board.display_mut()?, state.display_image.as_deref())
+                    {
+                        log::warn!("draw image failed: {:?}", err);
+                    }
+                }
+            }
+            self.shown_screen = Some(state.display_screen);
+            self.shown_passkey = None;
+            self.shown_chart = None;
+            self.shown_qr = None;
+            self.shown_clock = None;
+            self.dashboard.invalidate();
+            self.log_view.invalidate();
+            self.pages.invalidate();
+            if let Some(ferris) = self.ferris.as_mut() {
+                ferris.invalidate();
+            }
+            refresh = true;
+        }
+
+        // 输入事件只在多页面界面和温度曲线中使用
+        while let Some(event) = state.ui_events.pop_front() {
+            match state.display_screen {
+                DisplayScreen::Ui => {
+                    if let Some(action) = self.pages.handle(event) {
+                        apply_action(action, state);
+                    }
+                    refresh = true;
+                }
+                DisplayScreen::Chart => match event {
+                    InputEvent::Left | InputEvent::Up => {
+                        state.chart_range = state.chart_range.prev()
+                    }
+                    InputEvent::Right | InputEvent::Down | InputEvent::Select => {
+                        state.chart_range = state.chart_range.next()
+                    }
+                    InputEvent::Back => state.display_screen = DisplayScreen::Dashboard,
+                },
+                _ => {}
+            }
+        }
+
+        match state.display_screen {
+            DisplayScreen::Dashboard => {
+                // 配对码变化时刷新屏幕
+                if state.ble_pairing_passkey != self.shown_passkey {
+                    self.shown_passkey = state.ble_pairing_passkey;
+                    ui::draw_passkey(board.display_mut()?, self.shown_passkey)?;
+                    // 配对码清除后重绘被它遮住的行
+                    self.dashboard.invalidate();
+                    refresh = true;
+                }
+                if let Some(ferris) = self.ferris.as_mut() {
+                    ferris.update(board.display_mut()?, Instant::now())?;
+                }
+                if refresh {
+                    let data = board.dashboard_data(state);
+                    let display = board.display_mut()?;
+                    // 配对时不绘制和配对码重叠的行
+                    let covered = self
+                        .shown_passkey
+                        .map(|_| ui::passkey_area(display.bounding_box()));
+                    self.dashboard
+                        .draw(display, &data, self.font.as_mut(), covered)?;
+                }
+            }
+            DisplayScreen::Log => {
+                if refresh || loop_times % LOG_REFRESH_LOOPS == 0 {
+                    self.log_view.draw(board.display_mut()?)?;
+                }
+            }
+            // 图片只在切换画面时绘制一次
+            DisplayScreen::Image => {}
+            DisplayScreen::Qr => {
+                let (text, caption) = match &state.display_qr {
+                    QrContent::WebUi => match board.wifi_ip() {
+                        Some(ip) => {
+                            let url = format!("http://{ip}/");
+                            (url.clone(), url)
+                        }
+                        None => (String::new(), "wifi not connected".to_string()),
+                    },
+                    QrContent::Wifi => (
+                        qr::wifi_join_string(board.wifi_ssid(), board.wifi_password()),
+                        format!("WiFi: {}", board.wifi_ssid()),
+                    ),
+                    QrContent::Text(text) => (text.clone(), text.clone()),
+                };
+                if self.shown_qr.as_ref() != Some(&text) {
+                    if let Err(err) = qr::draw_qr_screen(board.display_mut()?, &text, &caption) {
+                        log::warn!("draw qr code failed: {:?}", err);
+                    }
+                    self.shown_qr = Some(text);
+                }
+            }
+            DisplayScreen::Clock => {
+                if clock::take_tick() || self.shown_clock.is_none() {
+                    let time = LocalTime::now(state.clock.utc_offset);
+                    let shown = (state.clock.clone(), time.map(|time| time.date()));
+                    if self.shown_clock.as_ref() != Some(&shown) {
+                        clock::draw_clock_screen(board.display_mut()?, &state.clock, time)?;
+                        self.shown_clock = Some(shown);
+                    } else if self.shown_clock_time != time {
+                        clock::draw_clock_time(board.display_mut()?, &state.clock, time)?;
+                    }
+                    self.shown_clock_time = time;
+                }
+            }
+            DisplayScreen::Chart => {
+                let range = state.chart_range;
+                let shown = (range, state.temperature_history.seq(range));
+                if self.shown_chart != Some(shown) {
+                    chart::draw_temperature_chart(
+                        board.display_mut()?,
+                        &state.temperature_history.points(range),
+                        range,
+                    )?;
+                    self.shown_chart = Some(shown);
+                }
+            }
+            DisplayScreen::Ui => {
+                if refresh {
+                    let data = board.dashboard_data(state);
+                    let panel = board.display_panel();
+                    pages::update_status(&mut self.pages, &data);
+                    pages::update_settings(
+                        &mut self.pages,
+                        &pages::Settings {
+                            rotation: state.display_config.map(|panel| panel.rotation_degrees()),
+                            mirrored: state.display_config.is_some_and(|panel| panel.mirrored),
+                            interval_ms: state.telemetry_interval.as_millis() as u64,
+                        },
+                    );
+                    pages::update_info(
+                        &mut self.pages,
+                        &pages::Info {
+                            version: env!("CARGO_PKG_VERSION"),
+                            panel: format!("{}x{}", panel.width, panel.height),
+                            free_heap: unsafe { sys::esp_get_free_heap_size() },
+                            ip: data.ip,
+                        },
+                    );
+                }
+                self.pages.draw(board.display_mut()?)?;
+            }
+            // 按顺序执行远程绘制命令
+            DisplayScreen::Canvas => {
+                while let Some(command) = state.display_commands.pop_front() {
+                    if let Err(err) =
+                        self.canvas
+                            .execute(board.display_mut()?, command, self.font.as_mut())
+                    {
+                        log::warn!("canvas command failed: {:?}", err);
+                    }
+                }
+            }
+        }
+        // 开启帧缓存时, 这一轮绘制的内容统一发送到屏幕
+        board
+            .display_mut()?
+            .flush()
+            .map_err(|e| anyhow!("display flush failed: {:?}", e))?;
+        Ok(())
+    }
+}
+
+/// 遥测通知间隔在设置页面中循环切换的值
+const TELEMETRY_INTERVALS: [Duration; 4] = [
+    Duration::from_millis(500),
+    Duration::from_millis(1000),
+    Duration::from_millis(2000),
+    Duration::from_millis(5000),
+];
+
+/// 处理设置页面中被选中的选项
+fn apply_action(action: UiAction, state: &mut BoardEsp32State) {
+    if action.page != pages::SETTINGS_PAGE {
+        return;
+    }
+    let Some(item) = SettingItem::ALL.get(action.index) else {
+        return;
+    };
+    match item {
+        SettingItem::Rotation => {
+            if let Some(panel) = state.display_config.as_mut() {
+                let rotation = (panel.rotation_degrees() + 90) % 360;
+                if let Err(err) = panel.set_option("rotation", &rotation.to_string()) {
+                    log::warn!("set rotation failed: {:?}", err);
+                }
+            }
+        }
+        SettingItem::Mirror => {
+            if let Some(panel) = state.display_config.as_mut() {
+                panel.mirrored = !panel.mirrored;
+            }
+        }
+        SettingItem::Interval => {
+            let next = TELEMETRY_INTERVALS
+                .iter()
+                .position(|interval| *interval == state.telemetry_interval)
+                .map_or(0, |i| (i + 1) % TELEMETRY_INTERVALS.len());
+            state.telemetry_interval = TELEMETRY_INTERVALS[next];
+        }
+        SettingItem::Exit => state.display_screen = DisplayScreen::Dashboard,
+    }
+}
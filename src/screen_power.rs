@@ -0,0 +1,61 @@
+//! 屏幕电源管理: 一段时间没有操作后关闭背光并让屏幕进入睡眠,
+//! 按键, ble 连接和远程绘制等操作唤醒屏幕并重新计时.
+
+use std::time::{Duration, Instant};
+
+/// 默认无操作多长时间后关闭屏幕
+pub const DEFAULT_SCREEN_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// 其他线程对屏幕电源的请求, 由主循环处理后清除
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerRequest {
+    /// 有操作, 唤醒屏幕并重新计时
+    Wake,
+    /// 立即关闭屏幕
+    Sleep,
+}
+
+/// 记录最后一次操作的时间, 决定屏幕什么时候睡眠
+pub struct ScreenPower {
+    last_activity: Instant,
+    asleep: bool,
+}
+
+impl Default for ScreenPower {
+    fn default() -> Self {
+        Self {
+            last_activity: Instant::now(),
+            asleep: false,
+        }
+    }
+}
+
+impl ScreenPower {
+    pub fn is_asleep(&self) -> bool {
+        self.asleep
+    }
+
+    /// 处理请求和超时, 屏幕需要切换状态时返回 Some(是否睡眠)
+    pub fn poll(
+        &mut self,
+        request: Option<PowerRequest>,
+        timeout: Option<Duration>,
+        now: Instant,
+    ) -> Option<bool> {
+        let sleep = match request {
+            Some(PowerRequest::Wake) => {
+                self.last_activity = now;
+                false
+            }
+            Some(PowerRequest::Sleep) => true,
+            None => {
+                self.asleep || timeout.is_some_and(|timeout| now - self.last_activity >= timeout)
+            }
+        };
+        if sleep == self.asleep {
+            return None;
+        }
+        self.asleep = sleep;
+        Some(sleep)
+    }
+}
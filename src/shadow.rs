@@ -0,0 +1,317 @@
+//! 屏幕内容的影子缓存, 所有绘制在发送到屏幕的同时写入内存,
+//! http 截图接口从这里读取, 不需要从屏幕回读显存.
+//!
+//! 开启 `use_psram_framebuffer` 特性后缓存作为帧缓存使用: 绘制只写内存并记录脏区域,
+//! 由主循环调用 `ShadowDisplay::flush` 把脏区域按矩形整块发送到屏幕.
+//!
+//! 整屏缓存有上百 KB, 只在 psram 中申请. 默认构建没有开启 psram,
+//! 不占用 wifi, ble 和 http 需要的内部内存, 截图接口不可用.
+
+use anyhow::{anyhow, Result};
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Dimensions, Point, Size},
+    pixelcolor::{raw::RawU16, Rgb565},
+    prelude::RawData,
+    primitives::{PointsIter, Rectangle},
+    Pixel,
+};
+use esp_idf_svc::sys;
+use std::sync::Mutex;
+
+/// 最多记录的脏区域个数, 超过后合并为一个
+const DIRTY_RECTS_MAX: usize = 8;
+
+static SHADOW: Mutex<ShadowBuffer> = Mutex::new(ShadowBuffer {
+    width: 0,
+    height: 0,
+    pixels: Vec::new(),
+    deferred: false,
+    dirty: Vec::new(),
+});
+
+/// RGB565 像素按行保存, 尺寸为旋转后的逻辑尺寸
+struct ShadowBuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<u16>,
+    /// 绘制先写入缓存, flush 时再发送到屏幕
+    deferred: bool,
+    /// 还没有发送到屏幕的区域
+    dirty: Vec<Rectangle>,
+}
+
+impl ShadowBuffer {
+    /// 屏幕尺寸变化时重新申请缓存, 没有 psram 或内存不够时关闭截图
+    fn resize(&mut self, size: Size) {
+        let len = size.width as usize * size.height as usize;
+        self.disable();
+        // 开启 psram 后大块内存由 malloc 优先从 psram 分配, psram 放不下时不申请,
+        // 否则会从内部内存分配
+        let psram_free = unsafe { sys::heap_caps_get_largest_free_block(sys::MALLOC_CAP_SPIRAM) };
+        if psram_free < len * core::mem::size_of::<u16>() {
+            log::info!("display shadow disabled: psram free block {psram_free} bytes");
+            return;
+        }
+        if let Err(err) = self.pixels.try_reserve_exact(len) {
+            log::warn!("display shadow disabled: {:?}", err);
+            return;
+        }
+        self.pixels.resize(len, 0);
+        self.width = size.width;
+        self.height = size.height;
+        self.deferred = cfg!(feature = "use_psram_framebuffer");
+    }
+
+    /// 释放缓存, 截图不可用, 绘制直接发送到屏幕
+    fn disable(&mut self) {
+        self.pixels = Vec::new();
+        self.dirty.clear();
+        self.width = 0;
+        self.height = 0;
+        self.deferred = false;
+    }
+
+    fn screen_area(&self) -> Rectangle {
+        Rectangle::new(Point::zero(), Size::new(self.width, self.height))
+    }
+
+    /// 记录脏区域, 相交或相邻的区域合并, 减少 flush 时设置窗口的次数
+    fn mark_dirty(&mut self, area: Rectangle) {
+        let mut area = area.intersection(&self.screen_area());
+        if area.is_zero_sized() {
+            return;
+        }
+        while let Some(i) = self.dirty.iter().position(|r| touches(r, &area)) {
+            area = union(&self.dirty.swap_remove(i), &area);
+        }
+        if self.dirty.len() >= DIRTY_RECTS_MAX {
+            area = self.dirty.drain(..).fold(area, |a, r| union(&a, &r));
+        }
+        self.dirty.push(area);
+    }
+
+    fn set(&mut self, point: Point, color: Rgb565) {
+        if point.x < 0 || point.y < 0 {
+            return;
+        }
+        let (x, y) = (point.x as u32, point.y as u32);
+        if x < self.width && y < self.height {
+            self.pixels[(y * self.width + x) as usize] = RawU16::from(color).into_inner();
+        }
+    }
+
+    fn fill(&mut self, area: &Rectangle, color: Rgb565) {
+        let area = area.intersection(&self.screen_area());
+        let Some(bottom_right) = area.bottom_right() else {
+            return;
+        };
+        let raw = RawU16::from(color).into_inner();
+        for y in area.top_left.y..=bottom_right.y {
+            let start = (y as u32 * self.width) as usize;
+            self.pixels[start + area.top_left.x as usize..=start + bottom_right.x as usize]
+                .fill(raw);
+        }
+    }
+}
+
+/// 两个矩形相交或者相邻
+fn touches(a: &Rectangle, b: &Rectangle) -> bool {
+    let grown = Rectangle::new(a.top_left - Point::new(1, 1), a.size + Size::new(2, 2));
+    !grown.intersection(b).is_zero_sized()
+}
+
+/// 包含两个矩形的最小矩形
+fn union(a: &Rectangle, b: &Rectangle) -> Rectangle {
+    let top_left = a.top_left.component_min(b.top_left);
+    let bottom_right = (a.top_left + a.size).component_max(b.top_left + b.size);
+    Rectangle::with_corners(top_left, bottom_right - Point::new(1, 1))
+}
+
+fn shadow() -> std::sync::MutexGuard<'static, ShadowBuffer> {
+    SHADOW.lock().expect("Could not lock display shadow")
+}
+
+/// 包装屏幕, 绘制时同时更新影子缓存
+pub struct ShadowDisplay<D> {
+    inner: D,
+}
+
+impl<D> ShadowDisplay<D>
+where
+    D: DrawTarget<Color = Rgb565>,
+{
+    pub fn new(inner: D) -> Self {
+        shadow().resize(inner.bounding_box().size);
+        Self { inner }
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// 不经过影子缓存直接访问屏幕, 用于睡眠等不绘制像素的命令
+    pub fn inner_mut(&mut self) -> &mut D {
+        &mut self.inner
+    }
+
+    /// 把脏区域发送到屏幕, 没有开启帧缓存时什么也不做
+    pub fn flush(&mut self) -> Result<(), D::Error> {
+        let mut shadow = shadow();
+        if !shadow.deferred {
+            return Ok(());
+        }
+        let dirty = std::mem::take(&mut shadow.dirty);
+        let width = shadow.width as usize;
+        for area in &dirty {
+            let left = area.top_left.x as usize;
+            let pixels = &shadow.pixels;
+            let colors = area.rows().flat_map(|y| {
+                let start = y as usize * width + left;
+                pixels[start..start + area.size.width as usize]
+                    .iter()
+                    .map(|raw| Rgb565::from(RawU16::new(*raw)))
+            });
+            self.inner.fill_contiguous(area, colors)?;
+        }
+        Ok(())
+    }
+}
+
+impl<D> Dimensions for ShadowDisplay<D>
+where
+    D: Dimensions,
+{
+    fn bounding_box(&self) -> Rectangle {
+        self.inner.bounding_box()
+    }
+}
+
+impl<D> DrawTarget for ShadowDisplay<D>
+where
+    D: DrawTarget<Color = Rgb565>,
+{
+    type Color = Rgb565;
+    type Error = D::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let mut shadow = shadow();
+        if shadow.deferred {
+            // 一次绘制只记录一个包含所有像素的脏区域
+            let mut bounds: Option<(Point, Point)> = None;
+            for Pixel(point, color) in pixels {
+                shadow.set(point, color);
+                bounds = Some(match bounds {
+                    Some((min, max)) => (min.component_min(point), max.component_max(point)),
+                    None => (point, point),
+                });
+            }
+            if let Some((min, max)) = bounds {
+                shadow.mark_dirty(Rectangle::with_corners(min, max));
+            }
+            return Ok(());
+        }
+        self.inner.draw_iter(
+            pixels
+                .into_iter()
+                .inspect(|Pixel(point, color)| shadow.set(*point, *color)),
+        )
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        let mut shadow = shadow();
+        let mut points = area.points();
+        if shadow.deferred {
+            for (point, color) in points.zip(colors) {
+                shadow.set(point, color);
+            }
+            shadow.mark_dirty(*area);
+            return Ok(());
+        }
+        self.inner.fill_contiguous(
+            area,
+            colors.into_iter().inspect(|color| {
+                if let Some(point) = points.next() {
+                    shadow.set(point, *color);
+                }
+            }),
+        )
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        let mut shadow = shadow();
+        shadow.fill(area, color);
+        if shadow.deferred {
+            shadow.mark_dirty(*area);
+            return Ok(());
+        }
+        self.inner.fill_solid(area, color)
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        let area = self.inner.bounding_box();
+        self.fill_solid(&area, color)
+    }
+}
+
+/// 当前屏幕尺寸, 没有屏幕或者缓存申请失败时返回 None
+pub fn screenshot_size() -> Option<Size> {
+    let shadow = shadow();
+    (shadow.width > 0).then(|| Size::new(shadow.width, shadow.height))
+}
+
+/// 把屏幕内容编码为 16 位 RGB565 BMP, 按行交给 write 发送.
+/// 每行单独加锁, 发送过程中不会阻塞主循环绘制
+pub fn write_bmp(mut write: impl FnMut(&[u8]) -> Result<()>) -> Result<()> {
+    let size = screenshot_size().ok_or(anyhow!("display shadow not available"))?;
+    let row_len = (size.width as usize * 2).next_multiple_of(4);
+    let header_len = 14 + 40 + 12;
+    let image_len = row_len * size.height as usize;
+
+    let mut header = Vec::with_capacity(header_len);
+    header.extend_from_slice(b"BM");
+    header.extend_from_slice(&((header_len + image_len) as u32).to_le_bytes());
+    header.extend_from_slice(&[0; 4]);
+    header.extend_from_slice(&(header_len as u32).to_le_bytes());
+    header.extend_from_slice(&40_u32.to_le_bytes());
+    header.extend_from_slice(&(size.width as i32).to_le_bytes());
+    // 高度为负表示从上到下保存
+    header.extend_from_slice(&(-(size.height as i32)).to_le_bytes());
+    header.extend_from_slice(&1_u16.to_le_bytes());
+    header.extend_from_slice(&16_u16.to_le_bytes());
+    // BI_BITFIELDS
+    header.extend_from_slice(&3_u32.to_le_bytes());
+    header.extend_from_slice(&(image_len as u32).to_le_bytes());
+    header.extend_from_slice(&[0; 16]);
+    for mask in [0xf800_u32, 0x07e0, 0x001f] {
+        header.extend_from_slice(&mask.to_le_bytes());
+    }
+    write(&header)?;
+
+    let mut row = vec![0_u8; row_len];
+    for y in 0..size.height {
+        {
+            let shadow = shadow();
+            // 截图过程中屏幕被重新配置, 剩下的行填黑色
+            if shadow.width == size.width && y < shadow.height {
+                let start = (y * size.width) as usize;
+                for (bytes, pixel) in row
+                    .chunks_exact_mut(2)
+                    .zip(&shadow.pixels[start..start + size.width as usize])
+                {
+                    bytes.copy_from_slice(&pixel.to_le_bytes());
+                }
+            } else {
+                row.fill(0);
+            }
+        }
+        write(&row)?;
+    }
+    Ok(())
+}
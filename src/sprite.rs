@@ -0,0 +1,145 @@
+//! 精灵动画: 按固定帧率在屏幕的一小块区域上播放帧序列, 每次只重绘这块区域, 不需要整屏刷新.
+//! 帧是 RGB565 小端原始像素, 所有帧竖直排列在同一个文件中, 可以用 `tools/mksprite.py` 生成.
+//! 编译进固件的动画使用 `include_bytes!` 并写明单帧尺寸,
+//! fat 中的动画和图片一样在文件名中带上单帧尺寸, 例如 `walk_32x32.raw`.
+
+use crate::image_loader;
+use anyhow::{anyhow, Result};
+use embedded_graphics::{
+    draw_target::{DrawTarget, DrawTargetExt},
+    geometry::{Point, Size},
+    image::{Image, ImageRawLE},
+    pixelcolor::Rgb565,
+    primitives::Rectangle,
+    Drawable,
+};
+use std::borrow::Cow;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// 没有指定帧率时的默认帧率, 主循环 50ms 一次, 最高 20 帧
+const DEFAULT_FPS: u32 = 10;
+
+/// 帧序列
+pub struct Sprite {
+    data: Cow<'static, [u8]>,
+    size: Size,
+    frames: usize,
+}
+
+impl Sprite {
+    /// 使用编译进固件的帧数据
+    pub fn from_static(data: &'static [u8], size: Size) -> Result<Self> {
+        Self::new(Cow::Borrowed(data), size)
+    }
+
+    /// 从 fat 中读取帧序列, 单帧尺寸从文件名中取出
+    pub fn load(path: &Path) -> Result<Self> {
+        let (width, height) = image_loader::raw_size_from_name(path)
+            .ok_or(anyhow!("sprite file name must end with _WxH.raw"))?;
+        Self::new(Cow::Owned(std::fs::read(path)?), Size::new(width, height))
+    }
+
+    fn new(data: Cow<'static, [u8]>, size: Size) -> Result<Self> {
+        let frame_len = size.width as usize * size.height as usize * 2;
+        if frame_len == 0 || data.is_empty() || !data.len().is_multiple_of(frame_len) {
+            return Err(anyhow!(
+                "sprite data of {} bytes is not a multiple of {}x{} frames",
+                data.len(),
+                size.width,
+                size.height
+            ));
+        }
+        let frames = data.len() / frame_len;
+        Ok(Self { data, size, frames })
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames
+    }
+
+    fn frame(&self, index: usize) -> ImageRawLE<'_, Rgb565> {
+        let frame_len = self.data.len() / self.frames;
+        let start = index % self.frames * frame_len;
+        ImageRawLE::new(&self.data[start..start + frame_len], self.size.width)
+    }
+}
+
+/// 在固定位置循环播放的动画
+pub struct Animation {
+    sprite: Sprite,
+    position: Point,
+    frame_interval: Duration,
+    /// 只在这个区域内绘制, 避免覆盖旁边的内容
+    clip: Option<Rectangle>,
+    frame: usize,
+    /// 下一帧的时间, None 表示下次更新时立即绘制当前帧
+    next_frame_at: Option<Instant>,
+}
+
+impl Animation {
+    pub fn new(sprite: Sprite, position: Point) -> Self {
+        Self {
+            sprite,
+            position,
+            frame_interval: Duration::from_millis(1000 / DEFAULT_FPS as u64),
+            clip: None,
+            frame: 0,
+            next_frame_at: None,
+        }
+    }
+
+    pub fn with_fps(mut self, fps: u32) -> Self {
+        self.frame_interval = Duration::from_millis(1000 / fps.max(1) as u64);
+        self
+    }
+
+    pub fn with_clip(mut self, clip: Rectangle) -> Self {
+        self.clip = Some(clip);
+        self
+    }
+
+    /// 屏幕内容被覆盖后调用, 下次更新时重绘当前帧
+    pub fn invalidate(&mut self) {
+        self.next_frame_at = None;
+    }
+
+    /// 到了下一帧的时间时切换并绘制, 返回是否绘制了
+    pub fn update<D>(&mut self, display: &mut D, now: Instant) -> Result<bool>
+    where
+        D: DrawTarget<Color = Rgb565>,
+        D::Error: core::fmt::Debug,
+    {
+        match self.next_frame_at {
+            Some(at) if now < at => return Ok(false),
+            Some(_) => self.frame = (self.frame + 1) % self.sprite.frame_count(),
+            None => {}
+        }
+        self.draw_frame(display, self.frame)?;
+        // 主循环被阻塞太久时不追赶落下的帧
+        self.next_frame_at = Some(match self.next_frame_at {
+            Some(at) if now - at < self.frame_interval => at + self.frame_interval,
+            _ => now + self.frame_interval,
+        });
+        Ok(true)
+    }
+
+    /// 绘制指定的帧, 超出裁剪区域的部分不绘制
+    pub fn draw_frame<D>(&self, display: &mut D, index: usize) -> Result<()>
+    where
+        D: DrawTarget<Color = Rgb565>,
+        D::Error: core::fmt::Debug,
+    {
+        let raw = self.sprite.frame(index);
+        let image = Image::new(&raw, self.position);
+        match &self.clip {
+            Some(clip) => image.draw(&mut display.clipped(clip)),
+            None => image.draw(display),
+        }
+        .map_err(|e| anyhow!("draw sprite failed: {:?}", e))
+    }
+}
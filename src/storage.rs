@@ -0,0 +1,24 @@
+use anyhow::{anyhow, Result};
+use std::path::{Component, Path, PathBuf};
+
+/// fat 文件系统挂载点
+pub const FS_MOUNT_POINT: &str = "/fat";
+/// 客户端传入路径的最大长度
+pub const FS_PATH_MAX: usize = 128;
+
+/// 把客户端传来的相对路径转换成挂载点下的路径, http 和 ble 的文件接口共用.
+/// 只允许普通的路径分量, 拒绝绝对路径和 `..`, 防止访问挂载点之外的文件
+pub fn fat_path(name: &str) -> Result<PathBuf> {
+    let name = name.trim_start_matches('/');
+    if name.is_empty() || name.len() > FS_PATH_MAX {
+        return Err(anyhow!("invalid path length: {}", name.len()));
+    }
+    let mut path = PathBuf::from(FS_MOUNT_POINT);
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            _ => return Err(anyhow!("invalid path: {name}")),
+        }
+    }
+    Ok(path)
+}
@@ -0,0 +1,71 @@
+//! ble 通知特征使用的二进制遥测数据, 不依赖 esp-idf, 编解码的测试在 simulator 中运行.
+//!
+//! 数据格式(小端):
+//!
+//! | 偏移 | 长度 | 内容                      |
+//! |------|------|---------------------------|
+//! | 0    | 1    | 版本号, 当前为 1          |
+//! | 1    | 1    | 状态标志, 见 `FLAG_*`     |
+//! | 2    | 4    | 计数器 u32                |
+//! | 6    | 2    | 温度 i16, 单位 0.01 摄氏度 |
+//! | 8    | 4    | 上电时间 u32, 单位 s       |
+
+#[cfg(test)]
+use anyhow::{anyhow, Result};
+
+/// 当前遥测数据版本
+pub const TELEMETRY_VERSION: u8 = 1;
+/// 版本 1 的数据长度
+pub const TELEMETRY_LEN: usize = 12;
+
+/// 文件系统挂载成功
+pub const FLAG_FS_READY: u8 = 1 << 0;
+/// 正在进行 ble 配对
+pub const FLAG_BLE_PAIRING: u8 = 1 << 1;
+/// 有外部传感器读数
+pub const FLAG_EXTERNAL_SENSOR: u8 = 1 << 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TelemetryRecord {
+    pub flags: u8,
+    pub counter: u32,
+    /// 摄氏度, 编码时保留两位小数
+    pub temperature: f32,
+    /// 上电时间, 单位 s
+    pub uptime: u32,
+}
+
+impl TelemetryRecord {
+    /// 编码成版本 1 的二进制数据
+    pub fn encode(&self) -> [u8; TELEMETRY_LEN] {
+        let temperature = (self.temperature * 100.0)
+            .round()
+            .clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        let mut buf = [0_u8; TELEMETRY_LEN];
+        buf[0] = TELEMETRY_VERSION;
+        buf[1] = self.flags;
+        buf[2..6].copy_from_slice(&self.counter.to_le_bytes());
+        buf[6..8].copy_from_slice(&temperature.to_le_bytes());
+        buf[8..12].copy_from_slice(&self.uptime.to_le_bytes());
+        buf
+    }
+
+    /// 解码二进制数据, 版本不支持或者长度不够时返回错误.
+    /// 允许数据比当前版本长, 方便以后在末尾追加字段. 固件只发送, 解码供客户端参考和测试使用
+    #[cfg(test)]
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let version = *data.first().ok_or(anyhow!("telemetry is empty"))?;
+        if version != TELEMETRY_VERSION {
+            return Err(anyhow!("unsupported telemetry version: {version}"));
+        }
+        if data.len() < TELEMETRY_LEN {
+            return Err(anyhow!("telemetry too short: {} bytes", data.len()));
+        }
+        Ok(Self {
+            flags: data[1],
+            counter: u32::from_le_bytes(data[2..6].try_into()?),
+            temperature: i16::from_le_bytes(data[6..8].try_into()?) as f32 / 100.0,
+            uptime: u32::from_le_bytes(data[8..12].try_into()?),
+        })
+    }
+}
@@ -0,0 +1,369 @@
+//! 与硬件无关的画面绘制, 只依赖 `DrawTarget<Color = Rgb565>`,
+//! 板子上绘制到屏幕, PC 上的模拟器(`simulator/`)绘制到内存中的帧缓存.
+
+use crate::image_loader;
+use crate::sprite::{Animation, Sprite};
+use anyhow::anyhow;
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Dimensions, Point, Size},
+    image::{Image, ImageRaw, ImageRawLE},
+    mono_font::{
+        ascii::{FONT_10X20, FONT_6X10},
+        MonoTextStyle, MonoTextStyleBuilder,
+    },
+    pixelcolor::Rgb565,
+    prelude::{RgbColor, WebColors},
+    primitives::{Primitive, PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+    Drawable,
+};
+use log::Level;
+use std::path::Path;
+
+/// 默认画面: 白色背景和 ferris
+pub fn draw_home<D>(display: &mut D) -> anyhow::Result<()>
+where
+    D: DrawTarget<Color = Rgb565>,
+    D::Error: core::fmt::Debug,
+{
+    display
+        .clear(Rgb565::WHITE)
+        .map_err(|e| anyhow!("clear display failed: {:?}", e))?;
+    let image_raw: ImageRawLE<Rgb565> = ImageRaw::new(include_bytes!("../assets/ferris.raw"), 86);
+    let image = Image::new(&image_raw, Point::new(26, 8));
+    image
+        .draw(display)
+        .map_err(|e| anyhow!("draw ferris failed: {:?}", e))?;
+    Ok(())
+}
+
+/// 状态面板上跳动的 ferris, 和默认画面中 ferris 静止时的位置重合, 不会画到配对码区域
+pub fn ferris_animation() -> anyhow::Result<Animation> {
+    let size = Size::new(86, 70);
+    let sprite = Sprite::from_static(include_bytes!("../assets/ferris_bounce.raw"), size)?;
+    let position = Point::new(26, 2);
+    Ok(Animation::new(sprite, position)
+        .with_fps(10)
+        .with_clip(Rectangle::with_corners(
+            position,
+            Point::new(position.x + size.width as i32 - 1, PASSKEY_AREA_TOP - 1),
+        )))
+}
+
+/// 配对码显示区域的起始行和高度, 位于 ferris 图片下方, 宽度和屏幕一致
+const PASSKEY_AREA_TOP: i32 = 80;
+const PASSKEY_AREA_HEIGHT: u32 = 48;
+
+/// 配对码显示区域, 状态面板不在这里绘制被遮住的行
+pub fn passkey_area(bounds: Rectangle) -> Rectangle {
+    Rectangle::new(
+        Point::new(bounds.top_left.x, PASSKEY_AREA_TOP),
+        Size::new(bounds.size.width, PASSKEY_AREA_HEIGHT),
+    )
+}
+
+/// 绘制蓝牙配对码, passkey 为 None 时清除该区域
+pub fn draw_passkey<D>(display: &mut D, passkey: Option<u32>) -> anyhow::Result<()>
+where
+    D: DrawTarget<Color = Rgb565>,
+    D::Error: core::fmt::Debug,
+{
+    let passkey_area = passkey_area(display.bounding_box());
+    passkey_area
+        .into_styled(PrimitiveStyle::with_fill(Rgb565::WHITE))
+        .draw(display)
+        .map_err(|e| anyhow!("clear passkey area failed: {:?}", e))?;
+    if let Some(passkey) = passkey {
+        let style = MonoTextStyle::new(&FONT_10X20, Rgb565::BLACK);
+        let center = passkey_area.center();
+        Text::with_alignment(
+            "BLE PIN",
+            center - Point::new(0, 6),
+            style,
+            Alignment::Center,
+        )
+        .draw(display)
+        .map_err(|e| anyhow!("draw passkey failed: {:?}", e))?;
+        Text::with_alignment(
+            &format!("{passkey:06}"),
+            center + Point::new(0, 16),
+            style,
+            Alignment::Center,
+        )
+        .draw(display)
+        .map_err(|e| anyhow!("draw passkey failed: {:?}", e))?;
+    }
+    Ok(())
+}
+
+/// 黑色背景上居中显示图片
+pub fn draw_image_screen<D>(display: &mut D, path: Option<&Path>) -> anyhow::Result<()>
+where
+    D: DrawTarget<Color = Rgb565>,
+    D::Error: core::fmt::Debug,
+{
+    display
+        .clear(Rgb565::BLACK)
+        .map_err(|e| anyhow!("clear display failed: {:?}", e))?;
+    let path = path.ok_or(anyhow!("no image selected"))?;
+    let info = image_loader::image_info(path)?;
+    let size = display.bounding_box().size;
+    let top_left = Point::new(
+        (size.width as i32 - info.width as i32) / 2,
+        (size.height as i32 - info.height as i32) / 2,
+    );
+    image_loader::draw_image(display, path, top_left)?;
+    Ok(())
+}
+
+/// 日志画面每行的高度和宽度能显示的字符数
+pub fn log_screen_size<D>(display: &D) -> (usize, usize)
+where
+    D: Dimensions,
+{
+    let bounds = display.bounding_box();
+    let char_size = FONT_6X10.character_size;
+    let columns = (bounds.size.width / char_size.width).max(1) as usize;
+    let rows = (bounds.size.height / char_size.height) as usize;
+    (columns, rows)
+}
+
+/// 日志画面的一行文字
+pub type LogLine = (Level, String);
+
+/// 以滚动终端的形式显示日志, 按屏幕宽度折行, 新日志在最下面, 超出屏幕的旧日志向上滚出
+pub fn draw_log_screen<D>(display: &mut D, records: &[(Level, String)]) -> anyhow::Result<()>
+where
+    D: DrawTarget<Color = Rgb565>,
+    D::Error: core::fmt::Debug,
+{
+    let lines = log_screen_lines(display, records);
+    draw_log_lines(display, &lines, None)
+}
+
+/// 按屏幕宽度折行, 只保留最后一屏
+pub fn log_screen_lines<D>(display: &D, records: &[(Level, String)]) -> Vec<LogLine>
+where
+    D: Dimensions,
+{
+    let (columns, rows) = log_screen_size(display);
+    let mut lines: Vec<LogLine> = Vec::new();
+    for (level, text) in records {
+        let text = format!("{} {}", level_tag(*level), text);
+        let chars: Vec<char> = text.chars().collect();
+        for chunk in chars.chunks(columns) {
+            lines.push((*level, chunk.iter().collect()));
+        }
+    }
+    let skip = lines.len().saturating_sub(rows);
+    lines.split_off(skip)
+}
+
+/// 绘制日志行. previous 为屏幕上已有的行, 只重绘内容变化的行, 文字带背景色直接覆盖,
+/// 不清屏, 没有帧缓存时也不闪烁; previous 为 None 时清屏后全部绘制
+pub fn draw_log_lines<D>(
+    display: &mut D,
+    lines: &[LogLine],
+    previous: Option<&[LogLine]>,
+) -> anyhow::Result<()>
+where
+    D: DrawTarget<Color = Rgb565>,
+    D::Error: core::fmt::Debug,
+{
+    let bounds = display.bounding_box();
+    let char_size = FONT_6X10.character_size;
+    let error = |e| anyhow!("draw log console failed: {:?}", e);
+    if previous.is_none() {
+        display.clear(Rgb565::BLACK).map_err(error)?;
+    }
+    let old_lines = previous.unwrap_or_default();
+    for row in 0..lines.len().max(old_lines.len()) {
+        let line = lines.get(row);
+        let old = old_lines.get(row);
+        if previous.is_some() && line == old {
+            continue;
+        }
+        let top = bounds.top_left + Point::new(0, (row as u32 * char_size.height) as i32);
+        let mut drawn_width = 0;
+        if let Some((level, text)) = line {
+            let style = MonoTextStyleBuilder::new()
+                .font(&FONT_6X10)
+                .text_color(level_color(*level))
+                .background_color(Rgb565::BLACK)
+                .build();
+            Text::with_baseline(text, top, style, Baseline::Top)
+                .draw(display)
+                .map_err(error)?;
+            drawn_width = text.chars().count() as u32 * char_size.width;
+        }
+        // 旧的行更长时擦掉剩下的部分
+        let old_width = old.map_or(0, |(_, text)| text.chars().count() as u32 * char_size.width);
+        if old_width > drawn_width {
+            Rectangle::new(
+                top + Point::new(drawn_width as i32, 0),
+                Size::new(old_width - drawn_width, char_size.height),
+            )
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
+            .draw(display)
+            .map_err(error)?;
+        }
+    }
+    Ok(())
+}
+
+fn level_tag(level: Level) -> char {
+    match level {
+        Level::Error => 'E',
+        Level::Warn => 'W',
+        Level::Info => 'I',
+        Level::Debug => 'D',
+        Level::Trace => 'V',
+    }
+}
+
+fn level_color(level: Level) -> Rgb565 {
+    match level {
+        Level::Error => Rgb565::RED,
+        Level::Warn => Rgb565::YELLOW,
+        Level::Info => Rgb565::WHITE,
+        Level::Debug => Rgb565::CYAN,
+        Level::Trace => Rgb565::CSS_GRAY,
+    }
+}
+
+/// 启动画面: 列出初始化阶段, 已完成的标记 ok, current 为正在进行的阶段, 下方显示进度条
+pub fn draw_boot_splash<D>(display: &mut D, stages: &[&str], current: usize) -> anyhow::Result<()>
+where
+    D: DrawTarget<Color = Rgb565>,
+    D::Error: core::fmt::Debug,
+{
+    let bounds = display.bounding_box();
+    let error = |e| anyhow!("draw boot splash failed: {:?}", e);
+    display.clear(Rgb565::BLACK).map_err(error)?;
+    let title_font = if bounds.size.height >= 240 {
+        &FONT_10X20
+    } else {
+        &FONT_6X10
+    };
+    Text::with_baseline(
+        "Booting",
+        bounds.top_left + Point::new(4, 4),
+        MonoTextStyle::new(title_font, Rgb565::WHITE),
+        Baseline::Top,
+    )
+    .draw(display)
+    .map_err(error)?;
+
+    let row_height = FONT_6X10.character_size.height as i32 + 4;
+    let top = bounds.top_left.y + title_font.character_size.height as i32 + 12;
+    for (i, stage) in stages.iter().enumerate() {
+        let (mark, color) = match i.cmp(&current) {
+            core::cmp::Ordering::Less => ("[ok]", Rgb565::GREEN),
+            core::cmp::Ordering::Equal => ("[..]", Rgb565::YELLOW),
+            core::cmp::Ordering::Greater => ("[  ]", Rgb565::CSS_GRAY),
+        };
+        Text::with_baseline(
+            &format!("{mark} {stage}"),
+            Point::new(bounds.top_left.x + 4, top + i as i32 * row_height),
+            MonoTextStyle::new(&FONT_6X10, color),
+            Baseline::Top,
+        )
+        .draw(display)
+        .map_err(error)?;
+    }
+
+    let bar = Rectangle::new(
+        Point::new(
+            bounds.top_left.x + 4,
+            bounds.top_left.y + bounds.size.height as i32 - 14,
+        ),
+        Size::new(bounds.size.width.saturating_sub(8), 8),
+    );
+    bar.into_styled(PrimitiveStyle::with_stroke(Rgb565::WHITE, 1))
+        .draw(display)
+        .map_err(error)?;
+    let done = current.min(stages.len()) as u32;
+    let filled = bar.size.width.saturating_sub(4) * done / (stages.len() as u32).max(1);
+    Rectangle::new(bar.top_left + Point::new(2, 2), Size::new(filled, 4))
+        .into_styled(PrimitiveStyle::with_fill(Rgb565::CSS_STEEL_BLUE))
+        .draw(display)
+        .map_err(error)?;
+    Ok(())
+}
+
+/// 致命错误画面: 显示出错的阶段和按屏幕宽度折行的错误信息.
+/// restarting 为 true 时表示马上重启, 否则表示上次启动 panic, 显示后继续启动
+pub fn draw_fatal_error<D>(
+    display: &mut D,
+    stage: &str,
+    message: &str,
+    restarting: bool,
+) -> anyhow::Result<()>
+where
+    D: DrawTarget<Color = Rgb565>,
+    D::Error: core::fmt::Debug,
+{
+    let bounds = display.bounding_box();
+    let error = |e| anyhow!("draw fatal error failed: {:?}", e);
+    let char_size = FONT_6X10.character_size;
+    display.clear(Rgb565::BLACK).map_err(error)?;
+    let header = Rectangle::new(bounds.top_left, Size::new(bounds.size.width, 16));
+    header
+        .into_styled(PrimitiveStyle::with_fill(Rgb565::RED))
+        .draw(display)
+        .map_err(error)?;
+    let title = if restarting {
+        "FATAL ERROR"
+    } else {
+        "LAST BOOT PANIC"
+    };
+    Text::with_text_style(
+        title,
+        header.center(),
+        MonoTextStyle::new(&FONT_6X10, Rgb565::WHITE),
+        TextStyleBuilder::new()
+            .alignment(Alignment::Center)
+            .baseline(Baseline::Middle)
+            .build(),
+    )
+    .draw(display)
+    .map_err(error)?;
+
+    let columns = (bounds.size.width / char_size.width).max(1) as usize;
+    let mut lines = vec![(format!("stage: {stage}"), Rgb565::YELLOW)];
+    for line in message.lines() {
+        let chars: Vec<char> = line.chars().collect();
+        for chunk in chars.chunks(columns) {
+            lines.push((chunk.iter().collect(), Rgb565::WHITE));
+        }
+    }
+    // 底部留一行显示提示
+    let rows = (bounds.size.height.saturating_sub(header.size.height + 4) / char_size.height)
+        .saturating_sub(1) as usize;
+    for (row, (line, color)) in lines.iter().take(rows).enumerate() {
+        let y = header.size.height as i32 + 4 + (row as u32 * char_size.height) as i32;
+        Text::with_baseline(
+            line,
+            bounds.top_left + Point::new(0, y),
+            MonoTextStyle::new(&FONT_6X10, *color),
+            Baseline::Top,
+        )
+        .draw(display)
+        .map_err(error)?;
+    }
+    let footer = if restarting {
+        "restarting..."
+    } else {
+        "continuing boot..."
+    };
+    Text::with_baseline(
+        footer,
+        bounds.top_left + Point::new(0, bounds.size.height as i32 - 1),
+        MonoTextStyle::new(&FONT_6X10, Rgb565::CSS_GRAY),
+        Baseline::Bottom,
+    )
+    .draw(display)
+    .map_err(error)?;
+    Ok(())
+}
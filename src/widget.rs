@@ -0,0 +1,451 @@
+//! 保留模式的界面控件: 标签, 进度条, 列表和页面.
+//! 控件保存自己的内容, 内容变化时只重绘对应的控件; 切换页面时整屏重绘.
+//! 页面之间通过输入事件切换, 输入来自按键或者控制台 `nav` 命令.
+
+use anyhow::anyhow;
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Point, Size},
+    mono_font::{
+        ascii::{FONT_10X20, FONT_6X10},
+        MonoFont, MonoTextStyle,
+    },
+    pixelcolor::Rgb565,
+    prelude::{RgbColor, WebColors},
+    primitives::{Primitive, PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+    Drawable,
+};
+
+/// 界面输入事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    /// 列表中选择上一项
+    Up,
+    /// 列表中选择下一项
+    Down,
+    /// 上一页
+    Left,
+    /// 下一页
+    Right,
+    /// 确认列表中选中的项
+    Select,
+    /// 回到第一页
+    Back,
+}
+
+impl InputEvent {
+    pub const NAMES: &'static str = "up down left right select back";
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "up" => Some(Self::Up),
+            "down" => Some(Self::Down),
+            "left" => Some(Self::Left),
+            "right" => Some(Self::Right),
+            "select" => Some(Self::Select),
+            "back" => Some(Self::Back),
+            _ => None,
+        }
+    }
+}
+
+/// 选中列表项后交给调用者处理的动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UiAction {
+    pub page: &'static str,
+    pub widget: &'static str,
+    pub index: usize,
+}
+
+/// 字体和行高, 和状态面板一样按屏幕高度选择
+#[derive(Clone, Copy)]
+struct Theme {
+    font: &'static MonoFont<'static>,
+    row_height: u32,
+}
+
+impl Theme {
+    fn for_size(size: Size) -> Self {
+        if size.height >= 240 {
+            Self {
+                font: &FONT_10X20,
+                row_height: 24,
+            }
+        } else {
+            Self {
+                font: &FONT_6X10,
+                row_height: 12,
+            }
+        }
+    }
+}
+
+pub struct Label {
+    text: String,
+    color: Rgb565,
+}
+
+pub struct ProgressBar {
+    /// 0~100
+    value: u8,
+    color: Rgb565,
+}
+
+pub struct List {
+    items: Vec<String>,
+    selected: usize,
+    /// 第一个可见项, 选中项超出显示范围时滚动
+    first: usize,
+}
+
+pub enum Widget {
+    Label(Label),
+    ProgressBar(ProgressBar),
+    List(List),
+}
+
+/// 页面中的控件, 位置在绘制时按顺序从上到下排列
+struct Slot {
+    key: &'static str,
+    widget: Widget,
+    area: Rectangle,
+    dirty: bool,
+}
+
+impl Widget {
+    pub fn label(text: impl Into<String>) -> Self {
+        Self::Label(Label {
+            text: text.into(),
+            color: Rgb565::BLACK,
+        })
+    }
+
+    pub fn progress_bar(value: u8) -> Self {
+        Self::ProgressBar(ProgressBar {
+            value: value.min(100),
+            color: Rgb565::CSS_STEEL_BLUE,
+        })
+    }
+
+    pub fn list(items: Vec<String>) -> Self {
+        Self::List(List {
+            items,
+            selected: 0,
+            first: 0,
+        })
+    }
+
+    /// 占用的行数, 列表占满页面剩余的空间
+    fn rows(&self) -> Option<u32> {
+        match self {
+            Self::Label(_) | Self::ProgressBar(_) => Some(1),
+            Self::List(_) => None,
+        }
+    }
+
+    /// 处理输入事件, 返回事件是否被使用, 以及被确认的列表项
+    fn handle(&mut self, event: InputEvent) -> (bool, Option<usize>) {
+        let Self::List(list) = self else {
+            return (false, None);
+        };
+        if list.items.is_empty() {
+            return (false, None);
+        }
+        match event {
+            InputEvent::Up => {
+                list.selected = list.selected.checked_sub(1).unwrap_or(list.items.len() - 1);
+                (true, None)
+            }
+            InputEvent::Down => {
+                list.selected = (list.selected + 1) % list.items.len();
+                (true, None)
+            }
+            InputEvent::Select => (true, Some(list.selected)),
+            _ => (false, None),
+        }
+    }
+
+    fn draw<D>(&mut self, display: &mut D, area: Rectangle, theme: Theme) -> anyhow::Result<()>
+    where
+        D: DrawTarget<Color = Rgb565>,
+        D::Error: core::fmt::Debug,
+    {
+        area.into_styled(PrimitiveStyle::with_fill(Rgb565::WHITE))
+            .draw(display)
+            .map_err(|e| anyhow!("clear widget failed: {:?}", e))?;
+        match self {
+            Self::Label(label) => {
+                let style = MonoTextStyle::new(theme.font, label.color);
+                Text::with_baseline(
+                    &label.text,
+                    area.top_left + Point::new(4, 2),
+                    style,
+                    Baseline::Top,
+                )
+                .draw(display)
+                .map_err(|e| anyhow!("draw label failed: {:?}", e))?;
+            }
+            Self::ProgressBar(bar) => {
+                let outline = Rectangle::new(
+                    area.top_left + Point::new(4, 2),
+                    area.size.saturating_sub(Size::new(8, 4)),
+                );
+                outline
+                    .into_styled(PrimitiveStyle::with_stroke(Rgb565::BLACK, 1))
+                    .draw(display)
+                    .map_err(|e| anyhow!("draw progress bar failed: {:?}", e))?;
+                let inner = outline.offset(-2);
+                let filled = Size::new(
+                    inner.size.width * u32::from(bar.value) / 100,
+                    inner.size.height,
+                );
+                Rectangle::new(inner.top_left, filled)
+                    .into_styled(PrimitiveStyle::with_fill(bar.color))
+                    .draw(display)
+                    .map_err(|e| anyhow!("draw progress bar failed: {:?}", e))?;
+            }
+            Self::List(list) => {
+                let visible = (area.size.height / theme.row_height).max(1) as usize;
+                if list.selected < list.first {
+                    list.first = list.selected;
+                } else if list.selected >= list.first + visible {
+                    list.first = list.selected + 1 - visible;
+                }
+                for (row, (index, item)) in list
+                    .items
+                    .iter()
+                    .enumerate()
+                    .skip(list.first)
+                    .take(visible)
+                    .enumerate()
+                {
+                    let row_area = Rectangle::new(
+                        area.top_left + Point::new(0, (row as u32 * theme.row_height) as i32),
+                        Size::new(area.size.width, theme.row_height),
+                    );
+                    // 选中项反色显示
+                    let (fg, bg) = if index == list.selected {
+                        (Rgb565::WHITE, Rgb565::BLACK)
+                    } else {
+                        (Rgb565::BLACK, Rgb565::WHITE)
+                    };
+                    row_area
+                        .into_styled(PrimitiveStyle::with_fill(bg))
+                        .draw(display)
+                        .map_err(|e| anyhow!("draw list failed: {:?}", e))?;
+                    Text::with_baseline(
+                        item,
+                        row_area.top_left + Point::new(4, 2),
+                        MonoTextStyle::new(theme.font, fg),
+                        Baseline::Top,
+                    )
+                    .draw(display)
+                    .map_err(|e| anyhow!("draw list failed: {:?}", e))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 一个页面, 顶部是标题栏, 下面是从上到下排列的控件
+pub struct Page {
+    name: &'static str,
+    title: String,
+    slots: Vec<Slot>,
+}
+
+impl Page {
+    pub fn new(name: &'static str, title: impl Into<String>) -> Self {
+        Self {
+            name,
+            title: title.into(),
+            slots: Vec::new(),
+        }
+    }
+
+    /// 添加控件, key 用于之后更新控件内容
+    pub fn with(mut self, key: &'static str, widget: Widget) -> Self {
+        self.slots.push(Slot {
+            key,
+            widget,
+            area: Rectangle::zero(),
+            dirty: true,
+        });
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn slot_mut(&mut self, key: &str) -> Option<&mut Slot> {
+        self.slots.iter_mut().find(|slot| slot.key == key)
+    }
+
+    /// 修改标签文字, 内容不变时不重绘
+    pub fn set_text(&mut self, key: &str, text: &str) {
+        if let Some(slot) = self.slot_mut(key) {
+            if let Widget::Label(label) = &mut slot.widget {
+                if label.text != text {
+                    label.text = text.to_string();
+                    slot.dirty = true;
+                }
+            }
+        }
+    }
+
+    pub fn set_progress(&mut self, key: &str, value: u8) {
+        if let Some(slot) = self.slot_mut(key) {
+            if let Widget::ProgressBar(bar) = &mut slot.widget {
+                let value = value.min(100);
+                if bar.value != value {
+                    bar.value = value;
+                    slot.dirty = true;
+                }
+            }
+        }
+    }
+
+    /// 修改列表内容, 保留选中的位置
+    pub fn set_items(&mut self, key: &str, items: Vec<String>) {
+        if let Some(slot) = self.slot_mut(key) {
+            if let Widget::List(list) = &mut slot.widget {
+                if list.items != items {
+                    list.selected = list.selected.min(items.len().saturating_sub(1));
+                    list.items = items;
+                    slot.dirty = true;
+                }
+            }
+        }
+    }
+
+    /// 按屏幕尺寸排列控件, 列表占满剩余空间
+    fn layout(&mut self, bounds: Rectangle, theme: Theme) {
+        let mut top = bounds.top_left.y + theme.row_height as i32 + 4;
+        let bottom = bounds.top_left.y + bounds.size.height as i32;
+        for slot in &mut self.slots {
+            let height = match slot.widget.rows() {
+                Some(rows) => rows * theme.row_height,
+                None => (bottom - top).max(0) as u32,
+            };
+            slot.area = Rectangle::new(
+                Point::new(bounds.top_left.x, top),
+                Size::new(bounds.size.width, height),
+            );
+            slot.dirty = true;
+            top += height as i32;
+        }
+    }
+}
+
+/// 多页面界面
+pub struct Ui {
+    pages: Vec<Page>,
+    current: usize,
+    /// 屏幕上当前显示的页面, None 表示需要整屏重绘
+    shown: Option<usize>,
+}
+
+impl Ui {
+    pub fn new(pages: Vec<Page>) -> Self {
+        Self {
+            pages,
+            current: 0,
+            shown: None,
+        }
+    }
+
+    pub fn page_mut(&mut self, name: &str) -> Option<&mut Page> {
+        self.pages.iter_mut().find(|page| page.name == name)
+    }
+
+    pub fn current_page(&self) -> Option<&'static str> {
+        self.pages.get(self.current).map(|page| page.name)
+    }
+
+    /// 屏幕内容被覆盖后调用, 下次绘制时整屏重绘
+    pub fn invalidate(&mut self) {
+        self.shown = None;
+    }
+
+    /// 先交给当前页面的控件处理, 没有控件使用的事件用于切换页面
+    pub fn handle(&mut self, event: InputEvent) -> Option<UiAction> {
+        let count = self.pages.len();
+        let page = self.pages.get_mut(self.current)?;
+        for slot in &mut page.slots {
+            let (used, selected) = slot.widget.handle(event);
+            if used {
+                slot.dirty = true;
+                return selected.map(|index| UiAction {
+                    page: page.name,
+                    widget: slot.key,
+                    index,
+                });
+            }
+        }
+        self.current = match event {
+            InputEvent::Left => (self.current + count - 1) % count,
+            InputEvent::Right => (self.current + 1) % count,
+            InputEvent::Back => 0,
+            _ => self.current,
+        };
+        None
+    }
+
+    /// 切换页面后整屏重绘, 否则只重绘内容有变化的控件
+    pub fn draw<D>(&mut self, display: &mut D) -> anyhow::Result<()>
+    where
+        D: DrawTarget<Color = Rgb565>,
+        D::Error: core::fmt::Debug,
+    {
+        let count = self.pages.len();
+        let Some(page) = self.pages.get_mut(self.current) else {
+            return Ok(());
+        };
+        let bounds = display.bounding_box();
+        let theme = Theme::for_size(bounds.size);
+        if self.shown != Some(self.current) {
+            display
+                .clear(Rgb565::WHITE)
+                .map_err(|e| anyhow!("clear display failed: {:?}", e))?;
+            let title_bar = Rectangle::new(
+                bounds.top_left,
+                Size::new(bounds.size.width, theme.row_height + 2),
+            );
+            title_bar
+                .into_styled(PrimitiveStyle::with_fill(Rgb565::CSS_STEEL_BLUE))
+                .draw(display)
+                .map_err(|e| anyhow!("draw title failed: {:?}", e))?;
+            let style = MonoTextStyle::new(theme.font, Rgb565::WHITE);
+            Text::with_baseline(
+                &page.title,
+                title_bar.top_left + Point::new(4, 2),
+                style,
+                Baseline::Top,
+            )
+            .draw(display)
+            .map_err(|e| anyhow!("draw title failed: {:?}", e))?;
+            // 右上角显示页码
+            Text::with_text_style(
+                &format!("{}/{}", self.current + 1, count),
+                title_bar.top_left + Point::new(title_bar.size.width as i32 - 4, 2),
+                style,
+                TextStyleBuilder::new()
+                    .alignment(Alignment::Right)
+                    .baseline(Baseline::Top)
+                    .build(),
+            )
+            .draw(display)
+            .map_err(|e| anyhow!("draw title failed: {:?}", e))?;
+            page.layout(bounds, theme);
+            self.shown = Some(self.current);
+        }
+        for slot in page.slots.iter_mut().filter(|slot| slot.dirty) {
+            slot.widget.draw(display, slot.area, theme)?;
+            slot.dirty = false;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+#!/usr/bin/env python3
+"""把 ttf/otf 字体转换成固件使用的 BFNT 位图字体, 格式说明见 src/font.rs.
+
+用法:
+    python3 tools/mkfont.py NotoSansSC-Regular.otf 16 ui16.bfnt --text "温度运行存储蓝牙信号地址"
+    python3 tools/mkfont.py NotoSansSC-Regular.otf 16 gb2312.bfnt --charset-file chars.txt
+
+默认包含可打印 ASCII 字符. 生成后把文件拷贝到板子的 /fat/fonts/ 下,
+或者生成到 assets/ui16.bfnt 并启用 embedded_ui_font feature 编译进固件.
+依赖 Pillow: pip install pillow
+"""
+
+import argparse
+import struct
+
+from PIL import Image, ImageDraw, ImageFont
+
+MAGIC = b"BFNT"
+VERSION = 1
+
+
+def render_glyph(font, char, height, ascent):
+    """返回 (宽度, 步进, 点阵字节), 点阵每行 ceil(宽度/8) 字节, 高位在左.
+    宽度和步进保存为 u8, 超过 255 时不能截断, 否则点阵的行长度和宽度对不上"""
+    advance = max(1, round(font.getlength(char)))
+    left, _, right, _ = font.getbbox(char)
+    width = max(advance, right)
+    if width > 255:
+        raise SystemExit(f"glyph {char!r} (U+{ord(char):04X}) is {width} px wide, max 255")
+    image = Image.new("1", (width, height), 0)
+    ImageDraw.Draw(image).text((0, ascent), char, font=font, fill=1, anchor="ls")
+    stride = (width + 7) // 8
+    bitmap = bytearray(stride * height)
+    for y in range(height):
+        for x in range(width):
+            if image.getpixel((x, y)):
+                bitmap[y * stride + x // 8] |= 0x80 >> (x % 8)
+    return width, advance, bytes(bitmap)
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__, formatter_class=argparse.RawDescriptionHelpFormatter)
+    parser.add_argument("font", help="ttf/otf 字体文件")
+    parser.add_argument("size", type=int, help="字号, 即行高像素")
+    parser.add_argument("output", help="输出的 bfnt 文件")
+    parser.add_argument("--text", default="", help="需要包含的字符")
+    parser.add_argument("--charset-file", help="包含需要字符的 utf-8 文本文件")
+    parser.add_argument("--no-ascii", action="store_true", help="不包含 ASCII 字符")
+    args = parser.parse_args()
+
+    chars = set(args.text)
+    if args.charset_file:
+        with open(args.charset_file, encoding="utf-8") as f:
+            chars |= set(f.read())
+    if not args.no_ascii:
+        chars |= {chr(c) for c in range(0x20, 0x7F)}
+    chars -= {"\n", "\r", "\t"}
+
+    if not 4 <= args.size <= 255:
+        raise SystemExit(f"size must be 4..255, got {args.size}")
+    font = ImageFont.truetype(args.font, args.size)
+    ascent, descent = font.getmetrics()
+    height = args.size
+    # 基线位置按字体的上下边距等比缩放到行高内
+    baseline = round(height * ascent / (ascent + descent))
+
+    codepoints = sorted(ord(c) for c in chars)
+    index = bytearray()
+    data = bytearray()
+    data_offset = 12 + 8 * len(codepoints)
+    for codepoint in codepoints:
+        width, advance, bitmap = render_glyph(font, chr(codepoint), height, baseline)
+        index += struct.pack("<II", codepoint, data_offset + len(data))
+        data += bytes([width, advance]) + bitmap
+
+    with open(args.output, "wb") as f:
+        f.write(MAGIC + bytes([VERSION, height, 0, 0]) + struct.pack("<I", len(codepoints)))
+        f.write(index)
+        f.write(data)
+    print(f"{args.output}: {len(codepoints)} glyphs, {12 + len(index) + len(data)} bytes")
+
+
+if __name__ == "__main__":
+    main()
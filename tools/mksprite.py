@@ -0,0 +1,80 @@
+#!/usr/bin/env python3
+"""生成固件使用的精灵动画帧序列, 格式说明见 src/sprite.rs.
+
+所有帧是 RGB565 小端原始像素, 竖直排列在同一个文件中. 放到 /fat 中时文件名带上单帧尺寸,
+例如 walk_32x32.raw, 编译进固件时在代码里写明尺寸.
+
+用法:
+    python3 tools/mksprite.py frames walk0.png walk1.png walk2.png -o walk_32x32.raw
+    python3 tools/mksprite.py bounce assets/ferris.raw 86 --frames 8 --height 6 -o assets/ferris_bounce.raw
+
+frames: 把几张尺寸相同的图片拼成帧序列, 依赖 Pillow: pip install pillow
+bounce: 用一张 RGB565 原始图片生成上下跳动的动画, 图片上方留出跳动的高度
+"""
+
+import argparse
+import math
+import struct
+
+
+def rgb565(r, g, b):
+    return ((r >> 3) << 11) | ((g >> 2) << 5) | (b >> 3)
+
+
+def load_frames(paths):
+    from PIL import Image
+
+    frames = [Image.open(path).convert("RGB") for path in paths]
+    size = frames[0].size
+    for path, frame in zip(paths, frames):
+        if frame.size != size:
+            raise SystemExit(f"{path}: size {frame.size} differs from {size}")
+    data = bytearray()
+    for frame in frames:
+        for r, g, b in frame.getdata():
+            data += struct.pack("<H", rgb565(r, g, b))
+    return size, len(frames), bytes(data)
+
+
+def bounce(raw, width, frames, height):
+    """第一帧和最后一帧在最下面, 中间的帧跳到最高, 空白部分填充图片左上角的颜色"""
+    row = width * 2
+    if len(raw) % row:
+        raise SystemExit(f"raw size {len(raw)} is not a multiple of width {width}")
+    rows = len(raw) // row
+    fill = raw[:2] * width
+    data = bytearray()
+    for i in range(frames):
+        lift = round(height * math.sin(math.pi * i / frames))
+        offset = height - lift
+        data += fill * offset + raw + fill * lift
+    return (width, rows + height), frames, bytes(data)
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    sub = parser.add_subparsers(dest="mode", required=True)
+    frames = sub.add_parser("frames")
+    frames.add_argument("images", nargs="+")
+    frames.add_argument("-o", "--output", required=True)
+    jump = sub.add_parser("bounce")
+    jump.add_argument("raw")
+    jump.add_argument("width", type=int)
+    jump.add_argument("--frames", type=int, default=8)
+    jump.add_argument("--height", type=int, default=6)
+    jump.add_argument("-o", "--output", required=True)
+    args = parser.parse_args()
+
+    if args.mode == "frames":
+        size, count, data = load_frames(args.images)
+    else:
+        with open(args.raw, "rb") as f:
+            raw = f.read()
+        size, count, data = bounce(raw, args.width, args.frames, args.height)
+    with open(args.output, "wb") as f:
+        f.write(data)
+    print(f"{args.output}: {count} frames of {size[0]}x{size[1]}, {len(data)} bytes")
+
+
+if __name__ == "__main__":
+    main()